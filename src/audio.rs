@@ -1,9 +1,11 @@
 use raycast::prelude::macroquad as mq;
 use mq::audio::{self, Sound, PlaySoundParams};
+use std::cell::Cell;
 use std::collections::HashMap;
 
 pub struct Audio {
     sounds: HashMap<&'static str, Sound>,
+    volume: Cell<f32>,
 }
 
 impl Audio {
@@ -18,8 +20,24 @@ impl Audio {
         sounds.insert("reload", audio::load_sound_from_bytes(include_bytes!("res/reload.wav")).await.unwrap());
         sounds.insert("dry", audio::load_sound_from_bytes(include_bytes!("res/dry.wav")).await.unwrap());
         sounds.insert("damage", audio::load_sound_from_bytes(include_bytes!("res/damage.wav")).await.unwrap());
+        sounds.insert("door", audio::load_sound_from_bytes(include_bytes!("res/door.wav")).await.unwrap());
+        sounds.insert("teleport", audio::load_sound_from_bytes(include_bytes!("res/teleport.wav")).await.unwrap());
+        sounds.insert("windup", audio::load_sound_from_bytes(include_bytes!("res/windup.wav")).await.unwrap());
 
-        Self { sounds }
+        Self {
+            sounds,
+            volume: Cell::new(1.),
+        }
+    }
+
+    pub fn has_sound(&self, name: &str) -> bool {
+        self.sounds.contains_key(name)
+    }
+
+    /// Applies to sounds played from now on and to the looping ones already playing
+    pub fn set_volume(&self, volume: f32) {
+        self.volume.set(volume);
+        audio::set_sound_volume(self.sounds.get("music").unwrap(), volume);
     }
 
     pub fn play_sound(&self, name: &str) {
@@ -27,7 +45,7 @@ impl Audio {
             self.sounds.get(name).unwrap(),
             PlaySoundParams {
                 looped: false,
-                volume: 1.,
+                volume: self.volume.get(),
             }
         );
     }
@@ -37,7 +55,7 @@ impl Audio {
             self.sounds.get(name).unwrap(),
             PlaySoundParams {
                 looped: true,
-                volume: 1.,
+                volume: self.volume.get(),
             }
         );
     }
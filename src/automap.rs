@@ -0,0 +1,139 @@
+use crate::layout::{self, Layout};
+use crate::surface::FOV;
+use raycast::prelude as rc;
+use rc::prelude::{macroquad, glam};
+use macroquad::prelude as mq;
+use glam::{Vec2, IVec2};
+use std::collections::HashSet;
+
+/// Rays cast across the view each frame to find the cells the player can see
+const EXPLORE_RAYS: usize = 48;
+/// Cells shown each side of the player on the minimap
+const MINIMAP_RADIUS: i32 = 8;
+const MINIMAP_CELL: f32 = 9.;
+const MARKER_SIZE: f32 = 3.;
+
+/// Top-down maps of the level, the full one only shows cells the player has seen
+pub struct Automap {
+    w: usize,
+    h: usize,
+    explored: Vec<bool>,
+    /// Cells in view this frame, things are only marked on the maps while they're in sight
+    visible: HashSet<IVec2>,
+    pub open: bool,
+}
+
+impl Automap {
+    pub fn new(layout: &Layout) -> Self {
+        let w: usize = layout.grid.iter().map(|row| row.len()).max().unwrap_or(0);
+        let h: usize = layout.grid.len();
+
+        Self {
+            w,
+            h,
+            explored: vec![false; w * h],
+            visible: HashSet::new(),
+            open: false,
+        }
+    }
+
+    /// Marks every cell within the camera's view, up to and including the walls it hits
+    pub fn explore(&mut self, map: &rc::Map, cam: &rc::Ray) {
+        self.visible.clear();
+        self.see(map.gpos(cam.orig));
+
+        let dir: Vec2 = cam.dir();
+        let angle: f32 = dir.y.atan2(dir.x);
+        let max_dist: f32 = map.w.max(map.h) * map.tsize;
+        for i in 0..=EXPLORE_RAYS {
+            let theta: f32 = angle - FOV / 2. + FOV * i as f32 / EXPLORE_RAYS as f32;
+            let ray: rc::Ray = rc::Ray::new(cam.orig, theta);
+            let dist: f32 = rc::cast_ray(map, [].iter(), &[], ray).distance.min(max_dist);
+
+            let mut d: f32 = 0.;
+            while d < dist + map.tsize / 2. {
+                self.see(map.gpos(ray.along(d)));
+                d += map.tsize / 2.;
+            }
+        }
+    }
+
+    fn see(&mut self, pos: IVec2) {
+        if pos.x < 0 || pos.y < 0 || pos.x as usize >= self.w || pos.y as usize >= self.h {
+            return;
+        }
+
+        self.explored[pos.y as usize * self.w + pos.x as usize] = true;
+        self.visible.insert(pos);
+    }
+
+    fn explored(&self, pos: IVec2) -> bool {
+        self.explored.get(pos.y as usize * self.w + pos.x as usize).copied().unwrap_or(false)
+    }
+
+    /// Grid around the player in the corner of the screen, with whatever's in sight marked
+    pub fn draw_minimap(&self, layout: &Layout, map: &rc::Map, cam: &rc::Ray, markers: &[(Vec2, mq::Color)], topleft: (f32, f32)) {
+        let size: f32 = (MINIMAP_RADIUS * 2 + 1) as f32 * MINIMAP_CELL;
+        let corner: Vec2 = Vec2::new(topleft.0 + rc::scrw() as f32 - size - 10., topleft.1 + 60.);
+        let center: Vec2 = corner + size / 2.;
+        let player: Vec2 = cam.orig / map.tsize;
+        let cell: IVec2 = map.gpos(cam.orig);
+        mq::draw_rectangle(corner.x, corner.y, size, size, mq::Color::new(0., 0., 0., 0.5));
+
+        for y in cell.y - MINIMAP_RADIUS..=cell.y + MINIMAP_RADIUS {
+            for x in cell.x - MINIMAP_RADIUS..=cell.x + MINIMAP_RADIUS {
+                if x < 0 || y < 0 || x as usize >= self.w || y as usize >= self.h {
+                    continue;
+                }
+
+                let pos: Vec2 = center + (Vec2::new(x as f32, y as f32) - player) * MINIMAP_CELL;
+                mq::draw_rectangle(pos.x, pos.y, MINIMAP_CELL, MINIMAP_CELL, cell_color(layout, x, y));
+            }
+        }
+
+        for (pos, color) in markers {
+            let gpos: IVec2 = map.gpos(*pos);
+            if self.visible.contains(&gpos) && (gpos - cell).abs().max_element() <= MINIMAP_RADIUS {
+                let screen: Vec2 = center + (*pos / map.tsize - player) * MINIMAP_CELL;
+                mq::draw_circle(screen.x, screen.y, MARKER_SIZE, *color);
+            }
+        }
+
+        draw_player(center, cam.dir(), MINIMAP_CELL);
+        mq::draw_rectangle_lines(corner.x, corner.y, size, size, 2., mq::WHITE);
+    }
+
+    /// Whole level scaled to the screen, only the explored part of it
+    pub fn draw_full(&self, layout: &Layout, map: &rc::Map, cam: &rc::Ray, topleft: (f32, f32)) {
+        let scrw: f32 = rc::scrw() as f32;
+        let scrh: f32 = rc::scrh() as f32;
+        mq::draw_rectangle(topleft.0, topleft.1, scrw, scrh, mq::Color::new(0., 0., 0., 0.85));
+
+        let cell: f32 = (scrw / self.w.max(1) as f32).min(scrh / self.h.max(1) as f32) * 0.9;
+        let corner: Vec2 = Vec2::new(topleft.0 + (scrw - cell * self.w as f32) / 2., topleft.1 + (scrh - cell * self.h as f32) / 2.);
+        for y in 0..self.h as i32 {
+            for x in 0..self.w as i32 {
+                if self.explored(IVec2::new(x, y)) {
+                    mq::draw_rectangle(corner.x + x as f32 * cell, corner.y + y as f32 * cell, cell, cell, cell_color(layout, x, y));
+                }
+            }
+        }
+
+        draw_player(corner + cam.orig / map.tsize * cell, cam.dir(), cell);
+        mq::draw_text("MAP  [tab] close", topleft.0 + 10., topleft.1 + scrh - 20., 24., mq::WHITE);
+    }
+}
+
+/// Start and key markers are left to the sprites, keys vanish once they're picked up
+fn cell_color(layout: &Layout, x: i32, y: i32) -> mq::Color {
+    match layout.at(x, y) {
+        'P' | 'R' | 'Y' | 'B' => layout::tile_color('.'),
+        tile => layout::tile_color(tile),
+    }
+}
+
+fn draw_player(pos: Vec2, dir: Vec2, cell: f32) {
+    let tip: Vec2 = pos + dir * cell * 1.5;
+    mq::draw_line(pos.x, pos.y, tip.x, tip.y, 2., mq::WHITE);
+    mq::draw_circle(pos.x, pos.y, cell / 3., mq::GREEN);
+}
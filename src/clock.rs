@@ -0,0 +1,31 @@
+use raycast::prelude as rc;
+use rc::prelude::macroquad;
+use macroquad::prelude as mq;
+use std::cell::Cell;
+
+thread_local! {
+    /// Total time spent in finished pauses
+    static PAUSED_FOR: Cell<f64> = const { Cell::new(0.) };
+    /// When the current pause began
+    static PAUSED_AT: Cell<Option<f64>> = const { Cell::new(None) };
+}
+
+/// Game time in seconds, it stands still while the game is paused
+pub fn now() -> f64 {
+    let real: f64 = PAUSED_AT.with(Cell::get).unwrap_or_else(mq::get_time);
+    real - PAUSED_FOR.with(Cell::get)
+}
+
+pub fn pause() {
+    PAUSED_AT.with(|at| {
+        if at.get().is_none() {
+            at.set(Some(mq::get_time()));
+        }
+    });
+}
+
+pub fn resume() {
+    if let Some(at) = PAUSED_AT.with(Cell::take) {
+        PAUSED_FOR.with(|paused| paused.set(paused.get() + mq::get_time() - at));
+    }
+}
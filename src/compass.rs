@@ -0,0 +1,51 @@
+use crate::surface::FOV;
+use raycast::prelude as rc;
+use rc::prelude::{macroquad, glam};
+use macroquad::prelude as mq;
+use glam::Vec2;
+use std::f32::consts::PI;
+
+/// Height of the marker when the target is in view
+const MARKER_Y: f32 = 160.;
+const MARKER_SIZE: f32 = 10.;
+/// Gap between the screen edge and the arrow when the target is out of view
+const EDGE_MARGIN: f32 = 30.;
+const COLOR: mq::Color = mq::GOLD;
+
+/// Marker above the target when it's on screen, otherwise an arrow on the side to turn towards
+pub fn draw(cam: &rc::Ray, target: Vec2, tsize: f32, topleft: (f32, f32)) {
+    let to: Vec2 = target - cam.orig;
+    let dir: Vec2 = cam.dir();
+    // Angle to the target relative to the facing, wrapped into -PI..PI
+    let mut rel: f32 = to.y.atan2(to.x) - dir.y.atan2(dir.x);
+    rel = (rel + PI).rem_euclid(2. * PI) - PI;
+
+    let cx: f32 = rc::scrw() as f32 / 2.;
+    let cy: f32 = rc::scrh() as f32 / 2.;
+    let distance: String = format!("{}m", (to.length() / tsize).round() as i32);
+    let measure = mq::measure_text(&distance, None, 20, 1.);
+
+    // Same projection as the renderer so the marker sits over the sprite
+    if rel.abs() < FOV / 2. {
+        let x: f32 = topleft.0 + cx + rel.tan() / (FOV / 2.).tan() * cx;
+        let y: f32 = topleft.1 + MARKER_Y;
+        mq::draw_triangle(
+            mq::vec2(x - MARKER_SIZE, y - MARKER_SIZE),
+            mq::vec2(x + MARKER_SIZE, y - MARKER_SIZE),
+            mq::vec2(x, y),
+            COLOR
+        );
+        mq::draw_text(&distance, x - measure.width / 2., y + 20., 20., COLOR);
+    } else {
+        let side: f32 = rel.signum();
+        let x: f32 = topleft.0 + cx + side * (cx - EDGE_MARGIN);
+        let y: f32 = topleft.1 + cy;
+        mq::draw_triangle(
+            mq::vec2(x - side * MARKER_SIZE, y - MARKER_SIZE),
+            mq::vec2(x - side * MARKER_SIZE, y + MARKER_SIZE),
+            mq::vec2(x + side * MARKER_SIZE, y),
+            COLOR
+        );
+        mq::draw_text(&distance, x - measure.width / 2., y + MARKER_SIZE + 20., 20., COLOR);
+    }
+}
@@ -0,0 +1,231 @@
+use crate::game;
+use crate::gamepad;
+use crate::input::Action;
+use crate::layout::{self, Layout};
+use crate::settings::Settings;
+use crate::surface::Surfaces;
+use raycast::prelude as rc;
+use rc::prelude::{macroquad, glam};
+use macroquad::prelude as mq;
+use glam::{Vec2, IVec2};
+use std::collections::HashMap;
+
+/// Height of the brush and help bar under the grid
+const BAR_HEIGHT: f32 = 60.;
+const MESSAGE_TIME: f64 = 2.;
+const PREVIEW_SPEED: f32 = 4.;
+
+/// Paintable tiles besides the declared walls
+const BRUSHES: [(char, &str); 11] = [
+    ('.', "floor"),
+    ('P', "player start"),
+    ('|', "door"),
+    ('r', "red door"),
+    ('y', "yellow door"),
+    ('b', "blue door"),
+    ('R', "red key"),
+    ('Y', "yellow key"),
+    ('B', "blue key"),
+    ('%', "acid"),
+    ('^', "spikes"),
+];
+
+/// First-person view of the map as it stands when the preview was opened
+struct Preview {
+    cam: rc::Ray,
+    map: rc::Map,
+    surfaces: Surfaces,
+    keys: Vec<rc::Entity>,
+}
+
+pub struct Editor {
+    /// File the map is saved to
+    path: String,
+    brushes: Vec<(char, String)>,
+    brush: usize,
+    message: Option<(String, f64)>,
+}
+
+impl Editor {
+    pub fn new(path: &str, layout: &Layout) -> Self {
+        // Floor first so it's the default, then the walls this map declares
+        let mut brushes: Vec<(char, String)> = vec![(BRUSHES[0].0, String::from(BRUSHES[0].1))];
+        brushes.extend(layout.walls.iter().map(|wall| (wall.tile, format!("wall {} ({})", wall.tile, wall.texture))));
+        brushes.extend(BRUSHES[1..].iter().map(|(tile, name)| (*tile, String::from(*name))));
+
+        Self {
+            path: String::from(path),
+            brushes,
+            brush: 0,
+            message: None,
+        }
+    }
+
+    /// Runs the editor until the editor binding is pressed again, leaving the edits in `layout`
+    pub async fn run(&mut self, layout: &mut Layout, settings: &Settings) {
+        rc::util::set_scrw_scrh(settings.resolution.0, settings.resolution.1);
+        mq::set_cursor_grab(false);
+        mq::show_mouse(true);
+
+        let textures: HashMap<char, mq::Image> = game::load_textures(layout);
+        let mut preview: Option<Preview> = None;
+        let mut prev_mpos: (f32, f32) = mq::mouse_position();
+
+        let mut out_img: mq::Image = mq::Image::gen_image_color(
+            rc::scrw() as u16,
+            rc::scrh() as u16,
+            mq::BLACK
+        );
+        let out_tex: mq::Texture2D = mq::Texture2D::from_image(&out_img);
+
+        let play: String = settings.bindings.get(Action::Editor).iter().map(|binding| binding.name().to_lowercase()).collect::<Vec<String>>().join("/");
+
+        // Input is read after a frame so the key that opened the editor doesn't close it again
+        mq::next_frame().await;
+        loop {
+            gamepad::update();
+            if settings.bindings.pressed(Action::Editor) {
+                return;
+            }
+
+            if mq::is_key_pressed(mq::KeyCode::Tab) {
+                preview = match preview {
+                    Some(_) => None,
+                    None => Some(self.open_preview(layout, &textures)),
+                };
+                mq::set_cursor_grab(preview.is_some());
+                mq::show_mouse(preview.is_none());
+                prev_mpos = mq::mouse_position();
+            }
+
+            let ctrl: bool = mq::is_key_down(mq::KeyCode::LeftControl) || mq::is_key_down(mq::KeyCode::RightControl);
+            if ctrl && mq::is_key_pressed(mq::KeyCode::S) {
+                let text: String = match std::fs::write(&self.path, layout.to_text()) {
+                    Ok(()) => format!("Saved {}", self.path),
+                    Err(err) => format!("Couldn't save {}: {}", self.path, err),
+                };
+                self.message = Some((text, mq::get_time()));
+            }
+
+            mq::clear_background(mq::BLACK);
+            match &mut preview {
+                Some(view) => {
+                    rc::util::fps_camera_controls(&view.map, &mut view.cam, PREVIEW_SPEED);
+                    rc::util::fps_camera_rotation(&mut view.cam, &mut prev_mpos, 0.5);
+
+                    out_img.bytes.fill(0);
+                    let cell: IVec2 = view.map.gpos(view.cam.orig);
+                    view.surfaces.apply(&mut view.map, cell);
+                    rc::render(&view.map, view.keys.iter(), view.cam, rc::Fog::None, &|| 0., &mut out_img);
+                    out_tex.update(&out_img);
+                    let topleft: (f32, f32) = rc::scr_topleft();
+                    mq::draw_texture(&out_tex, topleft.0, topleft.1, mq::WHITE);
+                    mq::draw_text(&format!("PREVIEW  [tab] grid  [{}] play", play), topleft.0 + 10., topleft.1 + 20., 24., mq::WHITE);
+                },
+                None => {
+                    self.paint(layout);
+                    self.draw_grid(layout, &play);
+                },
+            }
+
+            if let Some((text, time)) = &self.message {
+                if mq::get_time() - time < MESSAGE_TIME {
+                    let measure = mq::measure_text(text, None, 24, 1.);
+                    mq::draw_text(text, (mq::screen_width() - measure.width) / 2., mq::screen_height() / 2., 24., mq::WHITE);
+                }
+            }
+
+            mq::next_frame().await;
+        }
+    }
+
+    fn open_preview(&self, layout: &Layout, textures: &HashMap<char, mq::Image>) -> Preview {
+        let map: rc::Map = layout.build(textures);
+        let start: Vec2 = layout.find('P').first().map_or(Vec2::new(100., 100.), |pos| game::tile_center(&map, *pos));
+        let mut keys: Vec<rc::Entity> = Vec::new();
+        for key in layout::KEYS {
+            for pos in layout.find(key) {
+                keys.push(rc::Entity::new(game::tile_center(&map, pos), key, (10., 10.)));
+            }
+        }
+
+        Preview {
+            cam: rc::Ray::new(start, 0.),
+            surfaces: Surfaces::new(layout),
+            map,
+            keys,
+        }
+    }
+
+    /// Side length of a grid cell on screen
+    fn cell_size(layout: &Layout) -> f32 {
+        let w: f32 = layout.grid.iter().map(|row| row.len()).max().unwrap_or(1).max(1) as f32;
+        let h: f32 = layout.grid.len().max(1) as f32;
+        (mq::screen_width() / w).min((mq::screen_height() - BAR_HEIGHT) / h)
+    }
+
+    fn hovered(layout: &Layout) -> Option<IVec2> {
+        let cell: f32 = Self::cell_size(layout);
+        let (mx, my): (f32, f32) = mq::mouse_position();
+        let pos: IVec2 = IVec2::new((mx / cell).floor() as i32, (my / cell).floor() as i32);
+        let row: &Vec<char> = layout.grid.get(pos.y.max(0) as usize)?;
+        if pos.x < 0 || pos.y < 0 || pos.x as usize >= row.len() {
+            return None;
+        }
+
+        Some(pos)
+    }
+
+    fn paint(&mut self, layout: &mut Layout) {
+        let scroll: f32 = mq::mouse_wheel().1;
+        if scroll > 0. || mq::is_key_pressed(mq::KeyCode::RightBracket) {
+            self.brush = (self.brush + 1) % self.brushes.len();
+        }
+        if scroll < 0. || mq::is_key_pressed(mq::KeyCode::LeftBracket) {
+            self.brush = (self.brush + self.brushes.len() - 1) % self.brushes.len();
+        }
+
+        let pos: IVec2 = match Self::hovered(layout) {
+            Some(pos) => pos,
+            None => return,
+        };
+        let tile: char = if mq::is_mouse_button_down(mq::MouseButton::Left) {
+            self.brushes[self.brush].0
+        } else if mq::is_mouse_button_down(mq::MouseButton::Right) {
+            '.'
+        } else {
+            return;
+        };
+
+        // There's only one start, painting it moves it
+        if tile == 'P' {
+            for old in layout.find('P') {
+                layout.grid[old.y as usize][old.x as usize] = '.';
+            }
+        }
+        layout.grid[pos.y as usize][pos.x as usize] = tile;
+    }
+
+    fn draw_grid(&self, layout: &Layout, play: &str) {
+        let cell: f32 = Self::cell_size(layout);
+        for (y, row) in layout.grid.iter().enumerate() {
+            for (x, tile) in row.iter().enumerate() {
+                let (px, py): (f32, f32) = (x as f32 * cell, y as f32 * cell);
+                mq::draw_rectangle(px, py, cell, cell, layout::tile_color(*tile));
+                if *tile != '.' && cell >= 14. {
+                    mq::draw_text(&tile.to_string(), px + cell * 0.3, py + cell * 0.75, cell * 0.8, mq::BLACK);
+                }
+            }
+        }
+
+        if let Some(pos) = Self::hovered(layout) {
+            mq::draw_rectangle_lines(pos.x as f32 * cell, pos.y as f32 * cell, cell, cell, 2., mq::WHITE);
+        }
+
+        let bar: f32 = mq::screen_height() - BAR_HEIGHT;
+        let (tile, name): &(char, String) = &self.brushes[self.brush];
+        mq::draw_rectangle(10., bar + 10., 16., 16., layout::tile_color(*tile));
+        mq::draw_text(&format!("BRUSH: {}", name), 34., bar + 24., 24., mq::WHITE);
+        mq::draw_text(&format!("[lmb] paint  [rmb] erase  [wheel] brush  [tab] preview  [ctrl+s] save  [{}] play", play), 10., bar + 48., 18., mq::GRAY);
+    }
+}
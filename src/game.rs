@@ -1,4 +1,16 @@
 use crate::audio::Audio;
+use crate::automap::Automap;
+use crate::clock;
+use crate::compass;
+use crate::gamepad::{self, Look};
+use crate::grapple::{self, Grapple, GrappleMode};
+use crate::input::Action;
+use crate::layout::{self, Layout};
+use crate::level::Level;
+use crate::menu::{self, PauseChoice};
+use crate::player::Movement;
+use crate::settings::Settings;
+use crate::surface::Surfaces;
 use raycast::prelude as rc;
 use rc::prelude::{macroquad, glam};
 use macroquad::prelude as mq;
@@ -6,14 +18,62 @@ use glam::{Vec2, IVec2};
 use std::collections::HashMap;
 
 const MAX_ENTS: usize = 30;
-const MAX_AMMO: usize = 3;
+/// Most of each pickup kind lying around at once
+const MAX_AMMO_PICKUPS: usize = 3;
+const MAX_HEALTH_PICKUPS: usize = 2;
+const MAX_ARMOR_PICKUPS: usize = 2;
 const NUTS_GOAL: i32 = 5;
+const MAX_HEALTH: i32 = 100;
+const MAX_ARMOR: i32 = 100;
+const HEALTH_PICKUP: i32 = 40;
+const ARMOR_PICKUP: i32 = 50;
+const ENT_DAMAGE: i32 = 20;
+/// Fraction of incoming damage soaked up by armor
+const ARMOR_ABSORB: f32 = 2. / 3.;
+const ENT_SPACING: f32 = 16.;
+const ATTACK_RANGE: f32 = 10.;
+const ATTACK_REACH: f32 = 20.;
+const ATTACK_WINDUP: f64 = 0.5;
+const ATTACK_COOLDOWN: f64 = 1.5;
+const GIB_COUNT: usize = 6;
+const GIB_LIFETIME: f64 = 3.;
+const GRAPPLE_CONTROL: f32 = 0.1;
+/// Sprites the swing hook flies through instead of catching on, every sprite character belongs here
+const NON_SOLID: [char; 21] = ['e', 'E', 'D', 'f', 'F', 'G', 'd', 'x', 'c', 'h', 'g', 'n', 'a', 'm', 'H', 'A', 'R', 'Y', 'B', '~', 'T'];
+const HIT_KNOCKBACK: f32 = 8.;
+const GRAPPLE_BOUNCE: f32 = 3.;
+const PULL_SPEED: f32 = 12.;
+/// Distance from the player at which pulled things arrive
+const PULL_REACH: f32 = 25.;
+const STUN_TIME: f64 = 2.;
+const MESSAGE_TIME: f64 = 2.;
+const TELEPORT_COOLDOWN: f64 = 1.;
+/// Hazard damage it takes to knock an enemy down a damage state
+const ENT_HAZARD_HP: f32 = 25.;
+/// Hazard damage taken at once, big enough that armor's share of it rounds to less than all of it
+const HAZARD_TICK: f32 = 3.;
+/// Directions an enemy tries before giving up on a step that doesn't land on a hazard
+const HAZARD_TRIES: usize = 4;
+const FADE_TIME: f64 = 0.5;
+/// How long the death or victory is shown before the end screen
+const END_DELAY: f64 = 2.;
+/// Item names by index, in the order weapon cycling goes through them
+const WEAPONS: [&str; 3] = ["knife", "mg", "gun"];
 
 struct Entities {
     ents: Vec<rc::Entity>,
     speeds: Vec<f32>,
     death_timers: Vec<Option<f64>>,
     velocities: Vec<Vec2>,
+    attack_starts: Vec<Option<f64>>,
+    last_attacks: Vec<f64>,
+    /// When the grapple started pulling each entity in
+    pulled: Vec<Option<f64>>,
+    stun_ends: Vec<f64>,
+    last_teleports: Vec<f64>,
+    /// Grid cell each entity was in last frame, pads only fire when stepped onto
+    last_cells: Vec<IVec2>,
+    burns: Vec<f32>,
 }
 
 impl Entities {
@@ -23,6 +83,13 @@ impl Entities {
             speeds: Vec::new(),
             death_timers: Vec::new(),
             velocities: Vec::new(),
+            attack_starts: Vec::new(),
+            last_attacks: Vec::new(),
+            pulled: Vec::new(),
+            stun_ends: Vec::new(),
+            last_teleports: Vec::new(),
+            last_cells: Vec::new(),
+            burns: Vec::new(),
         }
     }
 
@@ -31,6 +98,13 @@ impl Entities {
         self.speeds.push(speed);
         self.death_timers.push(None);
         self.velocities.push(Vec2::ZERO);
+        self.attack_starts.push(None);
+        self.last_attacks.push(-100.);
+        self.pulled.push(None);
+        self.stun_ends.push(-100.);
+        self.last_teleports.push(-100.);
+        self.last_cells.push(IVec2::new(-1, -1));
+        self.burns.push(0.);
     }
 
     fn remove(&mut self, index: usize) {
@@ -38,22 +112,194 @@ impl Entities {
         self.speeds.remove(index);
         self.death_timers.remove(index);
         self.velocities.remove(index);
+        self.attack_starts.remove(index);
+        self.last_attacks.remove(index);
+        self.pulled.remove(index);
+        self.stun_ends.remove(index);
+        self.last_teleports.remove(index);
+        self.last_cells.remove(index);
+        self.burns.remove(index);
     }
+
+    fn flung(&self, index: usize) -> bool {
+        self.velocities[index].x.abs() > 0.001 || self.velocities[index].y.abs() > 0.001
+    }
+
+    fn stunned(&self, index: usize) -> bool {
+        clock::now() < self.stun_ends[index]
+    }
+
+    /// Moves an enemy to its next damage state, returns true if that killed it
+    fn damage(&mut self, index: usize) -> bool {
+        self.cancel_attack(index);
+        self.ents[index].texture = match self.ents[index].texture {
+            'e' => 'E',
+            'E' => 'D',
+            'D' => 'd',
+            _ => 'd',
+        };
+
+        if self.ents[index].texture == 'd' {
+            self.death_timers[index] = Some(clock::now());
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Drops a wind-up in progress, the cooldown restarts so the enemy doesn't swing again right away
+    fn cancel_attack(&mut self, index: usize) {
+        if self.attack_starts[index].take().is_some() {
+            self.ents[index].texture = base_texture(self.ents[index].texture);
+            self.last_attacks[index] = clock::now();
+        }
+    }
+}
+
+struct Gibs {
+    ents: Vec<rc::Entity>,
+    velocities: Vec<Vec2>,
+    spawn_times: Vec<f64>,
+}
+
+impl Gibs {
+    fn new() -> Self {
+        Self {
+            ents: Vec::new(),
+            velocities: Vec::new(),
+            spawn_times: Vec::new(),
+        }
+    }
+
+    fn spawn(&mut self, pos: Vec2) {
+        for _ in 0..GIB_COUNT {
+            let theta: f32 = mq::rand::gen_range(0., std::f32::consts::TAU);
+            self.ents.push(rc::Entity::new(pos, 'g', (8., 8.)));
+            self.velocities.push(Vec2::new(theta.cos(), theta.sin()) * mq::rand::gen_range(2., 6.));
+            self.spawn_times.push(clock::now());
+        }
+    }
+
+    fn update(&mut self, map: &rc::Map) {
+        for (ent, vel) in self.ents.iter_mut().zip(self.velocities.iter_mut()) {
+            if vel.length() > 0.01 {
+                ent.pos = rc::util::move_towards_collidable(map, ent.pos, ent.pos + *vel, vel.length());
+                *vel *= 0.9;
+            }
+        }
+
+        for i in (0..self.ents.len()).rev() {
+            if clock::now() - self.spawn_times[i] > GIB_LIFETIME {
+                self.ents.remove(i);
+                self.velocities.remove(i);
+                self.spawn_times.remove(i);
+            }
+        }
+    }
+}
+
+/// Wind-up sprite for an enemy texture, keeping its damage state
+fn windup_texture(texture: char) -> char {
+    match texture {
+        'e' => 'f',
+        'E' => 'F',
+        'D' => 'G',
+        _ => texture,
+    }
+}
+
+fn base_texture(texture: char) -> char {
+    match texture {
+        'f' => 'e',
+        'F' => 'E',
+        'G' => 'D',
+        _ => texture,
+    }
+}
+
+/// Takes armor's share of `damage` off the armor, as far as it goes, and the rest off health
+fn hurt_player(damage: i32, health: &mut i32, armor: &mut i32) {
+    let absorbed: i32 = ((damage as f32 * ARMOR_ABSORB).round() as i32).min(*armor);
+    *armor -= absorbed;
+    *health = (*health - (damage - absorbed)).max(0);
+}
+
+pub fn tile_center(map: &rc::Map, pos: IVec2) -> Vec2 {
+    (pos.as_vec2() + 0.5) * map.tsize
 }
 
-fn random_spot(map: &rc::Map) -> Vec2 {
-    let mut res: Vec2 = Vec2::default();
-    loop {
-        res.x = mq::rand::gen_range(0., map.w * map.tsize);
-        res.y = mq::rand::gen_range(0., map.h * map.tsize);
+/// Floor the player can get to from `cell` that's safe to spawn on, no hazards or teleporter pads
+fn spawn_spots(layout: &Layout, cell: IVec2) -> Vec<IVec2> {
+    layout.reachable(cell).into_iter().filter(|pos| layout.hurt(*pos) == 0. && layout.teleporter(*pos).is_none()).collect()
+}
+
+/// Random point on one of `cells` other than the player's, `None` when there's nowhere else
+fn random_spot(map: &rc::Map, cells: &[IVec2], player: IVec2) -> Option<Vec2> {
+    let cells: Vec<IVec2> = cells.iter().copied().filter(|cell| *cell != player).collect();
+    if cells.is_empty() {
+        return None;
+    }
 
-        let gpos: IVec2 = map.gpos(res);
-        if map.at(gpos.x, gpos.y) == '.' {
-            break;
+    let cell: IVec2 = cells[mq::rand::gen_range(0, cells.len()).min(cells.len() - 1)];
+    Some((cell.as_vec2() + Vec2::new(mq::rand::gen_range(0., 1.), mq::rand::gen_range(0., 1.))) * map.tsize)
+}
+
+/// Sprite, door and wall images keyed by their map character
+pub fn load_textures(layout: &Layout) -> HashMap<char, mq::Image> {
+    let mut textures: HashMap<char, mq::Image> = HashMap::new();
+    textures.insert('e', mq::Image::from_file_with_format(include_bytes!("res/shrek.png"), Some(mq::ImageFormat::Png)).unwrap());
+    textures.insert('E', mq::Image::from_file_with_format(include_bytes!("res/shrek-1.png"), Some(mq::ImageFormat::Png)).unwrap());
+    textures.insert('D', mq::Image::from_file_with_format(include_bytes!("res/shrek-2.png"), Some(mq::ImageFormat::Png)).unwrap());
+    textures.insert('f', mq::Image::from_file_with_format(include_bytes!("res/shrek-attack.png"), Some(mq::ImageFormat::Png)).unwrap());
+    textures.insert('F', mq::Image::from_file_with_format(include_bytes!("res/shrek-1-attack.png"), Some(mq::ImageFormat::Png)).unwrap());
+    textures.insert('G', mq::Image::from_file_with_format(include_bytes!("res/shrek-2-attack.png"), Some(mq::ImageFormat::Png)).unwrap());
+    textures.insert('d', mq::Image::from_file_with_format(include_bytes!("res/shrek_dead_gun.png"), Some(mq::ImageFormat::Png)).unwrap());
+    textures.insert('c', mq::Image::from_file_with_format(include_bytes!("res/shrek-corpse.png"), Some(mq::ImageFormat::Png)).unwrap());
+    textures.insert('h', mq::Image::from_file_with_format(include_bytes!("res/shrek-halved-corpse.png"), Some(mq::ImageFormat::Png)).unwrap());
+    textures.insert('g', mq::Image::from_file_with_format(include_bytes!("res/gib.png"), Some(mq::ImageFormat::Png)).unwrap());
+    textures.insert('n', mq::Image::from_file_with_format(include_bytes!("res/deez.png"), Some(mq::ImageFormat::Png)).unwrap());
+    textures.insert('a', mq::Image::from_file_with_format(include_bytes!("res/ammo.png"), Some(mq::ImageFormat::Png)).unwrap());
+    textures.insert('m', mq::Image::from_file_with_format(include_bytes!("res/mg-ammo.png"), Some(mq::ImageFormat::Png)).unwrap());
+    textures.insert('x', mq::Image::from_file_with_format(include_bytes!("res/shrek-halved.png"), Some(mq::ImageFormat::Png)).unwrap());
+    textures.insert('~', mq::Image::from_file_with_format(include_bytes!("res/rope.png"), Some(mq::ImageFormat::Png)).unwrap());
+    textures.insert('H', mq::Image::from_file_with_format(include_bytes!("res/health.png"), Some(mq::ImageFormat::Png)).unwrap());
+    textures.insert('A', mq::Image::from_file_with_format(include_bytes!("res/armor.png"), Some(mq::ImageFormat::Png)).unwrap());
+    textures.insert('|', mq::Image::from_file_with_format(include_bytes!("res/door.png"), Some(mq::ImageFormat::Png)).unwrap());
+    textures.insert('r', mq::Image::from_file_with_format(include_bytes!("res/door-red.png"), Some(mq::ImageFormat::Png)).unwrap());
+    textures.insert('y', mq::Image::from_file_with_format(include_bytes!("res/door-yellow.png"), Some(mq::ImageFormat::Png)).unwrap());
+    textures.insert('b', mq::Image::from_file_with_format(include_bytes!("res/door-blue.png"), Some(mq::ImageFormat::Png)).unwrap());
+    textures.insert(':', mq::Image::from_file_with_format(include_bytes!("res/door-open.png"), Some(mq::ImageFormat::Png)).unwrap());
+    textures.insert('S', mq::Image::from_file_with_format(include_bytes!("res/switch.png"), Some(mq::ImageFormat::Png)).unwrap());
+    textures.insert('s', mq::Image::from_file_with_format(include_bytes!("res/switch-on.png"), Some(mq::ImageFormat::Png)).unwrap());
+    textures.insert('T', mq::Image::from_file_with_format(include_bytes!("res/teleporter.png"), Some(mq::ImageFormat::Png)).unwrap());
+    textures.insert('R', mq::Image::from_file_with_format(include_bytes!("res/key-red.png"), Some(mq::ImageFormat::Png)).unwrap());
+    textures.insert('Y', mq::Image::from_file_with_format(include_bytes!("res/key-yellow.png"), Some(mq::ImageFormat::Png)).unwrap());
+    textures.insert('B', mq::Image::from_file_with_format(include_bytes!("res/key-blue.png"), Some(mq::ImageFormat::Png)).unwrap());
+    for wall in &layout.walls {
+        if let Some(image) = layout::wall_image(&wall.texture) {
+            textures.insert(wall.tile, image);
         }
     }
 
-    res
+    textures
+}
+
+/// Tally of a run shown on the end screens
+pub struct Stats {
+    /// Seconds played, not counting pauses
+    pub time: f64,
+    pub kills: u32,
+    pub nuts: i32,
+}
+
+/// Why a run of the game ended
+pub enum Exit {
+    Restart,
+    Edit,
+    /// Back to the main menu
+    Quit,
+    Dead(Stats),
+    Won(Stats),
 }
 
 pub struct Game {
@@ -61,8 +307,9 @@ pub struct Game {
 }
 
 impl Game {
-    pub async fn new() -> Self {
+    pub async fn new(settings: &Settings) -> Self {
         let audio: Audio = Audio::new().await;
+        audio.set_volume(settings.volume);
         audio.loop_sound("music");
 
         Self {
@@ -70,26 +317,34 @@ impl Game {
         }
     }
 
-    pub async fn run(&self) {
-        rc::util::set_scrw_scrh(800, 800);
-
-        let mut textures: HashMap<char, mq::Image> = HashMap::new();
-        textures.insert('0', mq::Image::from_file_with_format(include_bytes!("res/wall.png"), Some(mq::ImageFormat::Png)).unwrap());
-        textures.insert('e', mq::Image::from_file_with_format(include_bytes!("res/shrek.png"), Some(mq::ImageFormat::Png)).unwrap());
-        textures.insert('E', mq::Image::from_file_with_format(include_bytes!("res/shrek-1.png"), Some(mq::ImageFormat::Png)).unwrap());
-        textures.insert('D', mq::Image::from_file_with_format(include_bytes!("res/shrek-2.png"), Some(mq::ImageFormat::Png)).unwrap());
-        textures.insert('d', mq::Image::from_file_with_format(include_bytes!("res/shrek_dead_gun.png"), Some(mq::ImageFormat::Png)).unwrap());
-        textures.insert('n', mq::Image::from_file_with_format(include_bytes!("res/deez.png"), Some(mq::ImageFormat::Png)).unwrap());
-        textures.insert('a', mq::Image::from_file_with_format(include_bytes!("res/ammo.png"), Some(mq::ImageFormat::Png)).unwrap());
-        textures.insert('m', mq::Image::from_file_with_format(include_bytes!("res/mg-ammo.png"), Some(mq::ImageFormat::Png)).unwrap());
-        textures.insert('x', mq::Image::from_file_with_format(include_bytes!("res/shrek-halved.png"), Some(mq::ImageFormat::Png)).unwrap());
-        let mut map: rc::Map = rc::Map::from_bytes(include_bytes!("res/map"), textures);
-        map.floor_tex(rc::Surface::Color(mq::DARKGRAY.into()));
-        map.ceil_tex(rc::Surface::Color(mq::GRAY.into()));
+    pub fn set_volume(&self, volume: f32) {
+        self.audio.set_volume(volume);
+    }
+
+    pub async fn run(&self, level: &Level, mut layout: Layout, settings: &mut Settings) -> Exit {
+        rc::util::set_scrw_scrh(settings.resolution.0, settings.resolution.1);
+        self.audio.set_volume(settings.volume);
+
+        let textures: HashMap<char, mq::Image> = load_textures(&layout);
+        let mut map: rc::Map = layout.build(&textures);
+        let surfaces: Surfaces = Surfaces::new(&layout);
+        let mut automap: Automap = Automap::new(&layout);
 
         let mut ents: Entities = Entities::new();
+        let mut corpses: Vec<rc::Entity> = Vec::new();
+        let mut gibs: Gibs = Gibs::new();
         let mut nut: Vec<rc::Entity> = Vec::new();
-        let mut ammo_ents: Vec<rc::Entity> = Vec::new();
+        let mut pickups: Vec<rc::Entity> = Vec::new();
+
+        let mut key_ents: Vec<rc::Entity> = Vec::new();
+        for key in layout::KEYS {
+            for pos in layout.find(key) {
+                key_ents.push(rc::Entity::new(tile_center(&map, pos), key, (10., 10.)));
+            }
+        }
+        let mut keys: Vec<char> = Vec::new();
+
+        let pads: Vec<rc::Entity> = layout.teleporters.iter().map(|teleporter| rc::Entity::new(tile_center(&map, teleporter.pos), 'T', (20., 4.))).collect();
 
         let mut items: Vec<rc::Item> = vec![
             rc::Item::new("knife", include_bytes!("res/knife.png")),
@@ -102,10 +357,14 @@ impl Game {
         let shooting_gun: mq::Texture2D = mq::Texture2D::from_file_with_format(include_bytes!("res/gun-shoot.png"), Some(mq::ImageFormat::Png));
         let shooting_mg: mq::Texture2D = mq::Texture2D::from_file_with_format(include_bytes!("res/machine-gun-shoot.png"), Some(mq::ImageFormat::Png));
 
-        let mut cam: rc::Ray = rc::Ray::new(Vec2::new(100., 100.), 0.);
+        let start: Vec2 = layout.find('P').first().map_or(Vec2::new(100., 100.), |pos| tile_center(&map, *pos));
+        let mut cam: rc::Ray = rc::Ray::new(start, 0.);
+        let mut last_cell: IVec2 = map.gpos(cam.orig);
+        let mut last_teleport: f64 = -100.;
+        let mut movement: Movement = Movement::new();
         let mut prev_mpos: (f32, f32) = mq::mouse_position();
+        let mut look: Look = Look::new();
 
-        let mut grabbed: bool = true;
         mq::set_cursor_grab(true);
         mq::show_mouse(false);
 
@@ -114,10 +373,11 @@ impl Game {
             rc::scrh() as u16,
             mq::BLACK
         );
-        let out_tex: mq::Texture2D = mq::Texture2D::from_image(&out_img);
+        let mut out_tex: mq::Texture2D = mq::Texture2D::from_image(&out_img);
 
-        let mut grappling: bool = false;
-        let mut grapple_target: Vec2 = Vec2::default();
+        let mut grapple: Grapple = Grapple::new();
+        let mut hooked_pickup: Option<rc::Entity> = None;
+        let mut hooked_at: f64 = -100.;
 
         let mut ammo: i32 = 16;
         let mut inv_ammo: i32 = 32;
@@ -125,55 +385,126 @@ impl Game {
         let mut inv_mg_ammo: i32 = 100;
         let mut reload_start: Option<f64> = None;
 
-        let mut health: i32 = 5;
+        let mut health: i32 = MAX_HEALTH;
+        let mut armor: i32 = 0;
         let mut last_hurt: f64 = -100.;
+        let mut burn: f32 = 0.;
+        // Worked out when something first needs to spawn, and again after the level changes or the player teleports
+        let mut spots: Option<Vec<IVec2>> = None;
 
         let mut nuts_collected: i32 = 0;
+        let mut finished: bool = false;
+        let mut kills: u32 = 0;
+        let start_time: f64 = clock::now();
+        let mut end_time: Option<f64> = None;
 
         let mut shake_begin: f64 = -100.;
         let mut mg_shake_begin: f64 = -100.;
 
-        let mut wallh: f32;
-
         let mut mg_last_shot: f64 = -100.;
 
         let mut last_jab: f64 = -100.;
 
+        let mut message: Option<(String, f64)> = None;
+
+        // Input is read after a frame so the key that left the editor doesn't open it again
+        mq::next_frame().await;
         loop {
-            // wallh = mq::get_time().sin() as f32 + 3.;
-            wallh = 2.;
-            map.wall_height('0', wallh);
-            if mq::is_key_pressed(mq::KeyCode::Escape) {
-                grabbed = !grabbed;
-                mq::set_cursor_grab(grabbed);
-                mq::show_mouse(!grabbed);
+            layout.animate_walls(&mut map);
+            gamepad::update();
+            if settings.bindings.pressed(Action::Editor) {
+                return Exit::Edit;
+            }
+
+            if health == 0 && settings.bindings.pressed(Action::Restart) {
+                return Exit::Restart;
+            }
+
+            if settings.bindings.pressed(Action::Pause) {
+                clock::pause();
+                let choice: PauseChoice = menu::pause(settings, &out_tex).await;
+                clock::resume();
+                self.audio.set_volume(settings.volume);
+                match choice {
+                    PauseChoice::Resume => (),
+                    PauseChoice::Restart => return Exit::Restart,
+                    PauseChoice::Quit => return Exit::Quit,
+                }
+
+                // The render target was sized for the old resolution
+                if settings.resolution != (rc::scrw(), rc::scrh()) {
+                    rc::util::set_scrw_scrh(settings.resolution.0, settings.resolution.1);
+                    out_img = mq::Image::gen_image_color(rc::scrw() as u16, rc::scrh() as u16, mq::BLACK);
+                    out_tex = mq::Texture2D::from_image(&out_img);
+                }
+
+                mq::set_cursor_grab(true);
+                mq::show_mouse(false);
+                prev_mpos = mq::mouse_position();
+            }
+
+            if layout.update() {
+                map = layout.build(&textures);
+                spots = None;
             }
 
-            if health > 0 && nuts_collected < NUTS_GOAL {
+            if health > 0 && nuts_collected < NUTS_GOAL && !finished {
+                let mut actions: Vec<layout::Action> = Vec::new();
+
                 // Movement
-                if grappling {
-                    if cam.orig.distance(grapple_target) < if item == 0 { 40. } else { 20. } {
-                        grappling = false;
+                if grapple.active() {
+                    let anchor: Vec2 = grapple.anchor().unwrap();
+                    let input: Vec2 = settings.bindings.movement(&cam, GRAPPLE_CONTROL, settings.deadzone);
+                    if grapple.update(&map, &mut cam.orig, &mut movement.velocity, input) {
+                        movement.knockback((cam.orig - anchor).normalize_or_zero() * GRAPPLE_BOUNCE);
                         self.audio.play_sound("impact");
-                        shake_begin = mq::get_time();
-                    } else {
-                        cam.orig = rc::util::move_towards_collidable(&map, cam.orig, grapple_target, if item == 0 { 16. } else if item == 2 { 10. } else { 8. });
+                        shake_begin = clock::now();
                     }
                 } else {
-                    rc::util::fps_camera_controls(&map, &mut cam, if item == 0 { 4. } else if item == 2 { 3. } else { 2. });
+                    let wish: Vec2 = settings.bindings.movement(&cam, 1., settings.deadzone);
+                    movement.update(
+                        wish != Vec2::ZERO,
+                        settings.bindings.down(Action::Sprint),
+                        settings.bindings.down(Action::Crouch),
+                        item
+                    );
+                    movement.step(&map, &mut cam.orig, wish, item);
                 }
-                rc::util::fps_camera_rotation(&mut cam, &mut prev_mpos, 0.5);
+                rc::util::fps_camera_rotation(&mut cam, &mut prev_mpos, settings.sensitivity);
+                look.update(&mut cam, settings.deadzone, settings.look_accel);
 
                 // Misc keys
-                if (item == 2 || item == 1) && mq::is_key_pressed(mq::KeyCode::R) {
-                    reload_start = Some(mq::get_time());
+                if (item == 2 || item == 1) && settings.bindings.pressed(Action::Reload) {
+                    reload_start = Some(clock::now());
                     items[item].unequip();
                     self.audio.play_sound("reload");
                 }
 
+                if settings.bindings.pressed(Action::Use) {
+                    let front: IVec2 = map.gpos(cam.orig + cam.dir() * map.tsize * 0.8);
+                    let tile: char = layout.at(front.x, front.y);
+                    if let Some(switch_actions) = layout.press_switch(front) {
+                        actions.extend(switch_actions);
+                        map = layout.build(&textures);
+                        spots = None;
+                        self.audio.play_sound("dry");
+                    } else if layout::is_door(tile) {
+                        match layout::door_key(tile) {
+                            Some(key) if !keys.contains(&key) => {
+                                message = Some((format!("You need the {} key", layout::key_name(key)), clock::now()));
+                                self.audio.play_sound("dry");
+                            }
+                            _ => {
+                                layout.open_door(front);
+                                self.audio.play_sound("door");
+                            }
+                        }
+                    }
+                }
+
                 // Reloading
                 if let Some(start) = reload_start {
-                    if mq::get_time() - start > 2. {
+                    if clock::now() - start > 2. {
                         reload_start = None;
                         let n: i32 = if item == 2 { 16 } else { 50 };
                         let reloaded: i32 = (if item == 2 { inv_ammo } else { inv_mg_ammo }).min(n).min(n - if item == 2 { ammo } else { mg_ammo });
@@ -190,28 +521,35 @@ impl Game {
                 }
 
                 // Items
-                if item != 2 && mq::is_key_pressed(mq::KeyCode::Key3) {
+                if item != 2 && settings.bindings.pressed(Action::Weapon3) {
                     item = 2;
                     rc::equip_item(&mut items, "gun");
                 }
 
-                if item != 1 && mq::is_key_pressed(mq::KeyCode::Key2) {
+                if item != 1 && settings.bindings.pressed(Action::Weapon2) {
                     item = 1;
                     rc::equip_item(&mut items, "mg");
                 }
 
-                if item != 0 && mq::is_key_pressed(mq::KeyCode::Key1) {
+                if item != 0 && settings.bindings.pressed(Action::Weapon1) {
                     item = 0;
                     rc::equip_item(&mut items, "knife");
                 }
 
+                for (action, step) in [(Action::NextWeapon, 1), (Action::PrevWeapon, WEAPONS.len() - 1)] {
+                    if settings.bindings.pressed(action) {
+                        item = (item + step) % WEAPONS.len();
+                        rc::equip_item(&mut items, WEAPONS[item]);
+                    }
+                }
+
                 // Item use
-                if mq::is_mouse_button_pressed(mq::MouseButton::Left) {
+                if settings.bindings.pressed(Action::Fire) {
                     // Animation
                     match item {
                         2 => {
                             if ammo > 0 {
-                                mg_shake_begin = mq::get_time();
+                                mg_shake_begin = clock::now();
                                 items[item].texswap(&shooting_gun, 0.1);
                                 ammo -= 1;
                                 self.audio.play_sound("shoot");
@@ -220,76 +558,69 @@ impl Game {
                                 let ins: rc::Intersection = rc::cast_ray(&map, ents.ents.iter(), &['d'], cam);
                                 match ins.itype {
                                     rc::IntersectionType::Entity { index, .. } => {
-                                        // ents.remove(index);
-                                        ents.ents[index].texture = match ents.ents[index].texture {
-                                            'e' => 'E',
-                                            'E' => 'D',
-                                            'D' => 'd',
-                                            _ => 'd',
-                                        };
-
                                         self.audio.play_sound("damage");
-                                        if ents.ents[index].texture == 'd' {
-                                            ents.death_timers[index] = Some(mq::get_time());
+                                        if ents.damage(index) {
                                             self.audio.play_sound("death");
                                         }
                                     }
-                                    _ => (),
+                                    _ => {
+                                        if layout.hit_wall(map.gpos(cam.along(ins.distance + 1.))) {
+                                            map = layout.build(&textures);
+                                            spots = None;
+                                            self.audio.play_sound("impact");
+                                        }
+                                    }
                                 }
                             } else {
                                 self.audio.play_sound("dry");
                             }
                         }
                         0 => {
-                            items[0].jab(if grappling { Vec2::new(-50., -50.) } else { Vec2::new(-100., 100.) }, 0.05);
-                            last_jab = mq::get_time();
+                            items[0].jab(if grapple.active() { Vec2::new(-50., -50.) } else { Vec2::new(-100., 100.) }, 0.05);
+                            last_jab = clock::now();
+
+                            if layout.hit_wall(map.gpos(cam.orig + cam.dir() * map.tsize * 0.6)) {
+                                map = layout.build(&textures);
+                                spots = None;
+                                self.audio.play_sound("impact");
+                            }
 
                         }
                         _ => (),
                     }
                 }
 
-                if mq::is_mouse_button_down(mq::MouseButton::Left) {
-                    match item {
-                        1 => {
-                            if mq::get_time() - mg_last_shot > 0.1 {
-                                mg_last_shot = mq::get_time();
-                                mg_shake_begin = mq::get_time();
-                                if mg_ammo > 0 {
-                                    items[item].texswap(&shooting_mg, 0.1);
-                                    mg_ammo -= 1;
-                                    self.audio.play_sound("shoot");
-
-                                    // Cast gun ray
-                                    let ins: rc::Intersection = rc::cast_ray(&map, ents.ents.iter(), &['d'], cam);
-                                    match ins.itype {
-                                        rc::IntersectionType::Entity { index, .. } => {
-                                            // ents.remove(index);
-                                            ents.ents[index].texture = match ents.ents[index].texture {
-                                                'e' => 'E',
-                                                'E' => 'D',
-                                                'D' => 'd',
-                                                _ => 'd',
-                                            };
-
-                                            self.audio.play_sound("damage");
-                                            if ents.ents[index].texture == 'd' {
-                                                ents.death_timers[index] = Some(mq::get_time());
-                                                self.audio.play_sound("death");
-                                            }
-                                        }
-                                        _ => (),
-                                    }
-                                } else {
-                                    self.audio.play_sound("dry");
+                if settings.bindings.down(Action::Fire) && item == 1 && clock::now() - mg_last_shot > 0.1 {
+                    mg_last_shot = clock::now();
+                    mg_shake_begin = clock::now();
+                    if mg_ammo > 0 {
+                        items[item].texswap(&shooting_mg, 0.1);
+                        mg_ammo -= 1;
+                        self.audio.play_sound("shoot");
+
+                        // Cast gun ray
+                        let ins: rc::Intersection = rc::cast_ray(&map, ents.ents.iter(), &['d'], cam);
+                        match ins.itype {
+                            rc::IntersectionType::Entity { index, .. } => {
+                                self.audio.play_sound("damage");
+                                if ents.damage(index) {
+                                    self.audio.play_sound("death");
+                                }
+                            }
+                            _ => {
+                                if layout.hit_wall(map.gpos(cam.along(ins.distance + 1.))) {
+                                    map = layout.build(&textures);
+                                    spots = None;
+                                    self.audio.play_sound("impact");
                                 }
                             }
                         }
-                        _ => (),
+                    } else {
+                        self.audio.play_sound("dry");
                     }
                 }
 
-                if mq::get_time() - last_jab < 0.1 {
+                if clock::now() - last_jab < 0.1 {
                     let mut hit_ents: bool = false;
                     for (ent, (death, vel)) in ents.ents.iter_mut().zip(ents.death_timers.iter_mut().zip(ents.velocities.iter_mut())) {
                         if ent.texture == 'x' || vel.x.abs() > 0.001 || vel.y.abs() > 0.001 {
@@ -297,137 +628,459 @@ impl Game {
                         }
 
                         if ent.pos.distance(cam.orig) < 30. && (ent.pos - cam.orig).normalize().dot(cam.dir()) > 0.2 {
-                            if grappling {
-                                *vel = (grapple_target - cam.orig).normalize();
+                            if let Some(anchor) = grapple.anchor() {
+                                *vel = (anchor - cam.orig).normalize();
                                 hit_ents = true;
                             } else {
                                 ent.texture = 'x';
-                                *death = Some(mq::get_time());
+                                *death = Some(clock::now());
+                                gibs.spawn(ent.pos);
                                 self.audio.play_sound("damage");
                             }
                         }
                     }
 
                     if hit_ents {
-                        grappling = false;
+                        grapple.release();
                     }
                 }
 
-                if mq::is_mouse_button_pressed(mq::MouseButton::Right) {
-                    grappling = true;
-                    grapple_target = cam.along(rc::cast_ray(&map, ents.ents.iter(), &['e', 'd', 'x', 'a', 'm'], cam).distance);
-                    self.audio.play_sound("grapple");
+                if settings.bindings.pressed(Action::GrappleMode) {
+                    grapple.toggle_mode();
                 }
 
-                // Entity spawning
-                let rng: i32 = mq::rand::gen_range(0, 100);
-                if rng < 3 && ents.ents.len() < MAX_ENTS {
-                    let pos: Vec2 = random_spot(&map);
-                    ents.push(
-                        rc::Entity::new(pos, 'e', (20., 30.)),
-                        mq::rand::gen_range(1., 4.)
-                    );
+                if settings.bindings.pressed(Action::Map) {
+                    automap.open = !automap.open;
+                }
+
+                if settings.bindings.pressed(Action::Grapple) && grapple.ready() {
+                    match grapple.mode {
+                        GrappleMode::Swing => {
+                            let target: Vec2 = cam.along(rc::cast_ray(&map, ents.ents.iter(), &NON_SOLID, cam).distance);
+                            if grapple.fire(cam.orig, target) {
+                                self.audio.play_sound("grapple");
+                            } else {
+                                self.audio.play_sound("dry");
+                            }
+                        }
+                        GrappleMode::Pull => {
+                            // Pickups come after the enemies in the intersection index
+                            let ins: rc::Intersection = rc::cast_ray(&map, ents.ents.iter().chain(pickups.iter()), &['d', 'x'], cam);
+                            match ins.itype {
+                                rc::IntersectionType::Entity { index, .. } if ins.distance <= grapple::GRAPPLE_RANGE && hooked_pickup.is_none() => {
+                                    if index < ents.ents.len() {
+                                        ents.pulled[index] = Some(clock::now());
+                                        ents.cancel_attack(index);
+                                    } else {
+                                        hooked_pickup = Some(pickups.remove(index - ents.ents.len()));
+                                        hooked_at = clock::now();
+                                    }
+                                    self.audio.play_sound("grapple");
+                                    grapple.release();
+                                }
+                                _ => self.audio.play_sound("dry"),
+                            }
+                        }
+                    }
                 }
 
-                if (rng == 2 || rng == 3) && ammo_ents.len() < MAX_AMMO {
-                    ammo_ents.push(rc::Entity::new(random_spot(&map), if rng == 2 { 'a' } else { 'm' }, (20., 25.)));
+                if settings.bindings.released(Action::Grapple) && grapple.active() {
+                    grapple.release();
+                }
+
+                // Entity spawning, only where the player can get to
+                let cell: IVec2 = map.gpos(cam.orig);
+                if mq::rand::gen_range(0., 100.) < level.spawns.enemy && ents.ents.len() < MAX_ENTS {
+                    let spots: &Vec<IVec2> = spots.get_or_insert_with(|| spawn_spots(&layout, cell));
+                    if let Some(pos) = random_spot(&map, spots, cell) {
+                        ents.push(
+                            rc::Entity::new(pos, 'e', (20., 30.)),
+                            mq::rand::gen_range(1., 4.)
+                        );
+                    }
+                }
+
+                for (texture, rate, max) in [
+                    ('a', level.spawns.ammo, MAX_AMMO_PICKUPS),
+                    ('m', level.spawns.mg_ammo, MAX_AMMO_PICKUPS),
+                    ('H', level.spawns.health, MAX_HEALTH_PICKUPS),
+                    ('A', level.spawns.armor, MAX_ARMOR_PICKUPS),
+                ] {
+                    if mq::rand::gen_range(0., 100.) < rate && pickups.iter().filter(|pickup| pickup.texture == texture).count() < max {
+                        let spots: &Vec<IVec2> = spots.get_or_insert_with(|| spawn_spots(&layout, cell));
+                        if let Some(pos) = random_spot(&map, spots, cell) {
+                            pickups.push(rc::Entity::new(pos, texture, (20., 25.)));
+                        }
+                    }
                 }
 
                 if nut.is_empty() {
-                    nut.push(rc::Entity::new(random_spot(&map), 'n', (20., 20.)));
+                    let spots: &Vec<IVec2> = spots.get_or_insert_with(|| spawn_spots(&layout, cell));
+                    if let Some(pos) = random_spot(&map, spots, cell) {
+                        nut.push(rc::Entity::new(pos, 'n', (20., 20.)));
+                    }
                 }
 
-                // Ammo collect
-                for (i, ent) in ammo_ents.iter().enumerate() {
-                    if cam.orig.distance(ent.pos) < 20. {
-                        // Can't remove multiple ents in a singe loop, just get the rest next frame
-                        if ent.texture == 'a' {
-                            inv_ammo += 32;
-                        } else {
-                            inv_mg_ammo += 100;
+                // Reel in the hooked pickup, it gets collected once it reaches the player or dropped if it gets stuck
+                if let Some(mut pickup) = hooked_pickup.take() {
+                    pickup.pos = rc::util::move_towards_collidable(&map, pickup.pos, cam.orig, PULL_SPEED);
+                    if pickup.pos.distance(cam.orig) < PULL_REACH {
+                        pickup.pos = cam.orig;
+                        pickups.push(pickup);
+                    } else if grapple::pull_broken(&map, hooked_at, cam.orig, pickup.pos) {
+                        pickups.push(pickup);
+                    } else {
+                        hooked_pickup = Some(pickup);
+                    }
+                }
+
+                // Pickup collect
+                for i in (0..pickups.len()).rev() {
+                    if cam.orig.distance(pickups[i].pos) < 20. {
+                        match pickups[i].texture {
+                            'a' => inv_ammo += 32,
+                            'm' => inv_mg_ammo += 100,
+                            'H' => {
+                                // Leave it for later if already at full health
+                                if health >= MAX_HEALTH {
+                                    continue;
+                                }
+                                health = (health + HEALTH_PICKUP).min(MAX_HEALTH);
+                            }
+                            'A' => {
+                                if armor >= MAX_ARMOR {
+                                    continue;
+                                }
+                                armor = (armor + ARMOR_PICKUP).min(MAX_ARMOR);
+                            }
+                            _ => (),
                         }
-                        ammo_ents.remove(i);
+                        pickups.remove(i);
+                        self.audio.play_sound("ammo");
+                    }
+                }
+
+                // Key collect
+                for i in (0..key_ents.len()).rev() {
+                    if cam.orig.distance(key_ents[i].pos) < 20. {
+                        let key: char = key_ents.remove(i).texture;
+                        keys.push(key);
+                        message = Some((format!("Picked up the {} key", layout::key_name(key)), clock::now()));
                         self.audio.play_sound("ammo");
-                        break;
+                    }
+                }
+
+                // Teleporters, the player has to step onto a pad so standing on the exit doesn't bounce back
+                let cell: IVec2 = map.gpos(cam.orig);
+                if cell != last_cell && clock::now() - last_teleport > TELEPORT_COOLDOWN {
+                    if let Some(teleporter) = layout.teleporter(cell) {
+                        cam = rc::Ray::new(tile_center(&map, teleporter.dest), teleporter.angle);
+                        movement.velocity = Vec2::ZERO;
+                        if grapple.active() {
+                            grapple.release();
+                        }
+                        last_teleport = clock::now();
+                        spots = None;
+                        self.audio.play_sound("teleport");
+                    }
+                }
+                last_cell = map.gpos(cam.orig);
+
+                // Enemies too, so one left standing on an exit pad doesn't bounce between pads
+                for i in 0..ents.ents.len() {
+                    let cell: IVec2 = map.gpos(ents.ents[i].pos);
+                    let stepped: bool = cell != ents.last_cells[i];
+                    ents.last_cells[i] = cell;
+                    if !stepped || ents.death_timers[i].is_some() || clock::now() - ents.last_teleports[i] < TELEPORT_COOLDOWN {
+                        continue;
+                    }
+
+                    if let Some(teleporter) = layout.teleporter(cell) {
+                        if teleporter.enemies {
+                            ents.ents[i].pos = tile_center(&map, teleporter.dest);
+                            ents.last_teleports[i] = clock::now();
+                            ents.last_cells[i] = teleporter.dest;
+                            // Flung enemies keep flying, now in the exit's direction
+                            if ents.flung(i) {
+                                ents.velocities[i] = Vec2::new(teleporter.angle.cos(), teleporter.angle.sin()) * ents.velocities[i].length();
+                            }
+                        }
+                    }
+                }
+
+                // Hurting floors
+                burn += layout.hurt(map.gpos(cam.orig)) * mq::get_frame_time();
+
+                while burn >= HAZARD_TICK {
+                    burn -= HAZARD_TICK;
+                    hurt_player(HAZARD_TICK as i32, &mut health, &mut armor);
+                    last_hurt = clock::now();
+                }
+
+                for i in 0..ents.ents.len() {
+                    if ents.death_timers[i].is_some() {
+                        continue;
+                    }
+
+                    ents.burns[i] += layout.hurt(map.gpos(ents.ents[i].pos)) * mq::get_frame_time() / ENT_HAZARD_HP;
+                    if ents.burns[i] >= 1. {
+                        ents.burns[i] -= 1.;
+                        if ents.damage(i) {
+                            self.audio.play_sound("death");
+                        }
+                    }
+                }
+
+                // Map scripting
+                actions.extend(layout.fire_triggers(map.gpos(cam.orig)));
+                for action in actions {
+                    match action {
+                        layout::Action::Open(pos) => {
+                            if layout::is_door(layout.at(pos.x, pos.y)) {
+                                layout.open_door(pos);
+                                self.audio.play_sound("door");
+                            }
+                        }
+                        layout::Action::Wave(count) => {
+                            let cell: IVec2 = map.gpos(cam.orig);
+                            let spots: &Vec<IVec2> = spots.get_or_insert_with(|| spawn_spots(&layout, cell));
+                            for _ in 0..count {
+                                if let Some(pos) = random_spot(&map, spots, cell) {
+                                    ents.push(
+                                        rc::Entity::new(pos, 'e', (20., 30.)),
+                                        mq::rand::gen_range(1., 4.)
+                                    );
+                                }
+                            }
+                        }
+                        layout::Action::Sound(name) => {
+                            if self.audio.has_sound(&name) {
+                                self.audio.play_sound(&name);
+                            }
+                        }
+                        layout::Action::Message(text) => message = Some((text, clock::now())),
+                        layout::Action::End => finished = true,
                     }
                 }
 
                 // Nuts collect
-                if cam.orig.distance(nut[0].pos) < 20. {
+                if nut.first().is_some_and(|n| cam.orig.distance(n.pos) < 20.) {
                     nut.clear();
                     nuts_collected += 1;
                 }
 
-                // Remove dead entities
-                for (i, death) in ents.death_timers.iter().enumerate() {
-                    if let Some(death) = death {
-                        if mq::get_time() - *death > 1. {
+                // Replace expired dead entities with corpses
+                for i in (0..ents.ents.len()).rev() {
+                    if let Some(death) = ents.death_timers[i] {
+                        if clock::now() - death > 1. {
+                            let texture: char = if ents.ents[i].texture == 'x' { 'h' } else { 'c' };
+                            corpses.push(rc::Entity::new(ents.ents[i].pos, texture, (30., 10.)));
                             ents.remove(i);
+                            kills += 1;
+                        }
+                    }
+                }
+
+                if corpses.len() > settings.corpses {
+                    corpses.drain(..corpses.len() - settings.corpses);
+                }
+
+                gibs.update(&map);
+
+                // Flung entities knock down whoever they hit, passing their velocity along
+                for i in 0..ents.ents.len() {
+                    if ents.death_timers[i].is_some() || !ents.flung(i) {
+                        continue;
+                    }
+
+                    for j in 0..ents.ents.len() {
+                        if i == j || ents.death_timers[j].is_some() || ents.flung(j) {
+                            continue;
+                        }
+
+                        if ents.ents[i].pos.distance(ents.ents[j].pos) < ENT_SPACING {
+                            ents.cancel_attack(j);
+                            ents.velocities[j] = ents.velocities[i];
+                            ents.velocities[i] = Vec2::ZERO;
+                            ents.death_timers[i] = Some(clock::now());
+                            ents.ents[i].texture = 'x';
+                            gibs.spawn(ents.ents[i].pos);
+                            movement.blast(cam.orig, ents.ents[i].pos);
+                            self.audio.play_sound("impact");
+                            self.audio.play_sound("death");
+                            shake_begin = clock::now();
                             break;
                         }
                     }
                 }
 
                 // Move entities
-                for (ent, (speed, (dead, vel))) in ents.ents.iter_mut()
-                                                     .zip(ents.speeds.iter()
-                                                     .zip(ents.death_timers.iter_mut()
-                                                     .zip(ents.velocities.iter())))
-                {
-                    if dead.is_some() {
+                for i in 0..ents.ents.len() {
+                    // Winding up enemies hold still so the strike can be dodged
+                    if ents.death_timers[i].is_some() || ents.attack_starts[i].is_some() || ents.stunned(i) {
                         continue;
                     }
 
+                    let ent: &mut rc::Entity = &mut ents.ents[i];
+                    let vel: Vec2 = ents.velocities[i];
                     if vel.x.abs() > 0.001 || vel.y.abs() > 0.001 {
                         let orig_pos: Vec2 = ent.pos;
-                        ent.pos = rc::util::move_towards_collidable(&map, ent.pos, ent.pos + *vel, 16.);
-                        if ent.pos.distance(orig_pos + *vel * 16.) > 5. {
-                            *dead = Some(mq::get_time());
+                        ent.pos = rc::util::move_towards_collidable(&map, ent.pos, ent.pos + vel, 16.);
+                        if ent.pos.distance(orig_pos + vel * 16.) > 5. {
+                            if layout.hit_wall(map.gpos(ent.pos + vel.normalize_or_zero() * map.tsize * 0.5)) {
+                                map = layout.build(&textures);
+                                spots = None;
+                            }
+
+                            ents.death_timers[i] = Some(clock::now());
                             ent.texture = 'x';
+                            gibs.spawn(ent.pos);
+                            movement.blast(cam.orig, ent.pos);
                             self.audio.play_sound("impact");
                             self.audio.play_sound("death");
-                            shake_begin = mq::get_time();
+                            shake_begin = clock::now();
+                        }
+                    } else if let Some(start) = ents.pulled[i] {
+                        ent.pos = rc::util::move_towards_collidable(&map, ent.pos, cam.orig, PULL_SPEED);
+                        if ent.pos.distance(cam.orig) < PULL_REACH {
+                            ents.pulled[i] = None;
+                            ents.stun_ends[i] = clock::now() + STUN_TIME;
+                            self.audio.play_sound("impact");
+                        } else if grapple::pull_broken(&map, start, cam.orig, ent.pos) {
+                            ents.pulled[i] = None;
                         }
                     } else {
+                        // Steer around hazards unless already standing in one
                         let diff: Vec2 = cam.orig - ent.pos;
-                        let theta: f32 = f32::atan2(diff.y, diff.x) + mq::rand::gen_range(-1.5, 1.5);
-                        let dir: Vec2 = Vec2::new(theta.cos(), theta.sin());
+                        let on_hazard: bool = layout.hurt(map.gpos(ent.pos)) > 0.;
+                        for _ in 0..HAZARD_TRIES {
+                            let theta: f32 = f32::atan2(diff.y, diff.x) + mq::rand::gen_range(-1.5, 1.5);
+                            let dir: Vec2 = Vec2::new(theta.cos(), theta.sin());
+
+                            let next: Vec2 = rc::util::move_towards_collidable(&map, ent.pos, ent.pos + dir, ents.speeds[i]);
+                            if on_hazard || layout.hurt(map.gpos(next)) <= 0. {
+                                ent.pos = next;
+                                break;
+                            }
+                        }
+                    }
+                }
+
+                // Separate crowded entities
+                let mut pushes: Vec<Vec2> = vec![Vec2::ZERO; ents.ents.len()];
+                for i in 0..ents.ents.len() {
+                    if ents.death_timers[i].is_some() || ents.flung(i) {
+                        continue;
+                    }
+
+                    for j in (i + 1)..ents.ents.len() {
+                        if ents.death_timers[j].is_some() || ents.flung(j) {
+                            continue;
+                        }
+
+                        let diff: Vec2 = ents.ents[i].pos - ents.ents[j].pos;
+                        let dist: f32 = diff.length();
+                        if dist < ENT_SPACING {
+                            let dir: Vec2 = if dist > 0.001 {
+                                diff / dist
+                            } else {
+                                let theta: f32 = mq::rand::gen_range(0., std::f32::consts::TAU);
+                                Vec2::new(theta.cos(), theta.sin())
+                            };
 
-                        ent.pos = rc::util::move_towards_collidable(&map, ent.pos, ent.pos + dir, *speed);
+                            let overlap: f32 = (ENT_SPACING - dist) / 2.;
+                            pushes[i] += dir * overlap;
+                            pushes[j] -= dir * overlap;
+                        }
                     }
                 }
 
-                // Entities damage
-                for (ent, death) in ents.ents.iter().zip(ents.death_timers.iter()) {
-                    if death.is_none() && mq::get_time() - last_hurt >= 1. && cam.orig.distance(ent.pos) < 5. {
-                        health -= 1;
-                        last_hurt = mq::get_time();
+                for (ent, push) in ents.ents.iter_mut().zip(pushes.iter()) {
+                    if push.length() > 0.001 {
+                        ent.pos = rc::util::move_towards_collidable(&map, ent.pos, ent.pos + *push, push.length());
+                    }
+                }
+
+                // Entity attacks
+                for i in 0..ents.ents.len() {
+                    if ents.death_timers[i].is_some() {
+                        continue;
+                    }
+
+                    if ents.flung(i) || ents.pulled[i].is_some() || ents.stunned(i) {
+                        ents.cancel_attack(i);
+                        continue;
+                    }
+
+                    let dist: f32 = cam.orig.distance(ents.ents[i].pos);
+                    match ents.attack_starts[i] {
+                        None => {
+                            if clock::now() - ents.last_attacks[i] >= ATTACK_COOLDOWN && dist < ATTACK_RANGE {
+                                ents.attack_starts[i] = Some(clock::now());
+                                ents.ents[i].texture = windup_texture(ents.ents[i].texture);
+                                self.audio.play_sound("windup");
+                            }
+                        }
+                        Some(start) => {
+                            if clock::now() - start >= ATTACK_WINDUP {
+                                ents.cancel_attack(i);
+
+                                if dist < ATTACK_REACH {
+                                    hurt_player(ENT_DAMAGE, &mut health, &mut armor);
+                                    last_hurt = clock::now();
+                                    movement.knockback((cam.orig - ents.ents[i].pos).normalize_or_zero() * HIT_KNOCKBACK);
+                                    self.audio.play_sound("impact");
+                                    shake_begin = clock::now();
+                                }
+                            }
+                        }
                     }
                 }
             } else {
-                // Restart
-                if mq::is_key_pressed(mq::KeyCode::Q) {
-                    return;
+                let end: f64 = *end_time.get_or_insert(clock::now());
+                if clock::now() - end > END_DELAY {
+                    // Enemies still dying when the run ended count too
+                    let stats: Stats = Stats {
+                        time: end - start_time,
+                        kills: kills + ents.death_timers.iter().filter(|death| death.is_some()).count() as u32,
+                        nuts: nuts_collected,
+                    };
+                    return if health == 0 { Exit::Dead(stats) } else { Exit::Won(stats) };
                 }
             }
 
+            automap.explore(&map, &cam);
+
             mq::clear_background(mq::BLACK);
             out_img.bytes.fill(0);
-            rc::render(&map, ents.ents.iter().chain(nut.iter()).chain(ammo_ents.iter()), cam, rc::Fog::None, &|| 0., &mut out_img);
+            let eye_offset: f32 = movement.eye_offset();
+            let mut rope: Vec<rc::Entity> = grapple.rope(cam.orig + cam.dir() * 10.);
+            // The rope lets go of an enemy as soon as it dies
+            for (ent, (pulled, death)) in ents.ents.iter().zip(ents.pulled.iter().zip(ents.death_timers.iter())) {
+                if pulled.is_some() && death.is_none() {
+                    rope.extend(grapple::rope_beads(cam.orig + cam.dir() * 10., ent.pos));
+                }
+            }
+            if let Some(pickup) = &hooked_pickup {
+                rope.extend(grapple::rope_beads(cam.orig + cam.dir() * 10., pickup.pos));
+            }
+            let cell: IVec2 = map.gpos(cam.orig);
+            surfaces.apply(&mut map, cell);
+            rc::render(&map, ents.ents.iter().chain(nut.iter()).chain(pickups.iter()).chain(key_ents.iter()).chain(pads.iter()).chain(corpses.iter()).chain(gibs.ents.iter()).chain(rope.iter()).chain(hooked_pickup.iter()), cam, rc::Fog::None, &|| eye_offset, &mut out_img);
             out_tex.update(&out_img);
             let topleft: (f32, f32) = rc::scr_topleft();
-            let shake: (f32, f32) = if mq::get_time() - shake_begin < 0.1 {
+            let shake: (f32, f32) = if clock::now() - shake_begin < 0.1 {
                 (mq::rand::gen_range(-10., 10.), mq::rand::gen_range(-10., 10.))
             } else {
                 (0., 0.)
             };
 
-            let mg_shake: (f32, f32) = if mq::get_time() - mg_shake_begin < 0.05 {
+            let mg_shake: (f32, f32) = if clock::now() - mg_shake_begin < 0.05 {
                 (mq::rand::gen_range(-5., 5.), mq::rand::gen_range(-5., 5.))
             } else {
                 (0., 0.)
             };
-            mq::draw_texture(&out_tex, topleft.0 + shake.0 + mg_shake.0, topleft.1 + shake.1 + mg_shake.1, mq::WHITE);
+            mq::draw_texture(&out_tex, topleft.0 + (shake.0 + mg_shake.0) * settings.shake, topleft.1 + (shake.1 + mg_shake.1) * settings.shake, mq::WHITE);
             rc::render_item(&mut items);
 
             let cx: f32 = rc::scrw() as f32 / 2.;
@@ -440,27 +1093,60 @@ impl Game {
                 mq::draw_text(format!("INVENTORY: {}", if item == 2 { inv_ammo } else { inv_mg_ammo }).as_str(), topleft.0 + 10., topleft.1 + rc::scrh() as f32 - 20., 24., mq::WHITE);
             }
 
+            mq::draw_rectangle(topleft.0 + cx - 100., topleft.1 + rc::scrh() as f32 - 16., 200., 6., mq::Color::new(0., 0., 0., 0.5));
+            mq::draw_rectangle(topleft.0 + cx - 100., topleft.1 + rc::scrh() as f32 - 16., 200. * movement.stamina(), 6., if movement.exhausted() { mq::ORANGE } else { mq::YELLOW });
+
             mq::draw_text(format!("HEALTH: {}", health).as_str(), topleft.0 + 10., topleft.1 + 20., 24., mq::WHITE);
-            mq::draw_text(format!("NUTS:   {}", nuts_collected).as_str(), topleft.0 + 10., topleft.1 + 40., 24., mq::WHITE);
+            mq::draw_text(format!("ARMOR:  {}", armor).as_str(), topleft.0 + 10., topleft.1 + 40., 24., mq::SKYBLUE);
+            mq::draw_text(format!("NUTS:   {}", nuts_collected).as_str(), topleft.0 + 10., topleft.1 + 60., 24., mq::WHITE);
+            mq::draw_text(if grapple.mode == GrappleMode::Swing { "GRAPPLE: SWING" } else { "GRAPPLE: PULL" }, topleft.0 + 10., topleft.1 + 80., 24., mq::WHITE);
 
             mq::draw_text(format!("FPS {}", mq::get_fps()).as_str(), topleft.0 + rc::scrw() as f32 - 80., topleft.1 + 20., 24., mq::WHITE);
 
-            if health == 0 || mq::get_time() - last_hurt < 1. {
-                mq::draw_rectangle(topleft.0, topleft.1, rc::scrw() as f32, rc::scrh() as f32, mq::Color::new(1., 0., 0., (1. - (mq::get_time() - last_hurt)) as f32 * 0.5));
+            for (i, key) in keys.iter().enumerate() {
+                let color: mq::Color = match key {
+                    'R' => mq::RED,
+                    'Y' => mq::YELLOW,
+                    _ => mq::BLUE,
+                };
+                mq::draw_rectangle(topleft.0 + rc::scrw() as f32 - 30. - i as f32 * 20., topleft.1 + 30., 14., 14., color);
             }
 
-            if health == 0 || nuts_collected == NUTS_GOAL {
-                mq::draw_rectangle(topleft.0, topleft.1, rc::scrw() as f32, rc::scrh() as f32, mq::Color::new(0., 0., 0., 0.5));
+            if automap.open {
+                automap.draw_full(&layout, &map, &cam, topleft);
+            } else {
+                let mut markers: Vec<(Vec2, mq::Color)> = ents.ents.iter().map(|ent| (ent.pos, mq::RED)).collect();
+                markers.extend(pickups.iter().map(|pickup| (pickup.pos, match pickup.texture {
+                    'H' => mq::GREEN,
+                    'A' => mq::SKYBLUE,
+                    _ => mq::YELLOW,
+                })));
+                markers.extend(key_ents.iter().map(|key| (key.pos, layout::tile_color(key.texture))));
+                markers.extend(nut.iter().map(|nut| (nut.pos, mq::GOLD)));
+                automap.draw_minimap(&layout, &map, &cam, &markers, topleft);
+
+                if let Some(target) = nut.first().filter(|_| settings.difficulty.compass()) {
+                    compass::draw(&cam, target.pos, map.tsize, topleft);
+                }
             }
 
-            if health == 0 {
-                let text: &str = "Press [q] to restart";
-                let measure = mq::measure_text(text, None, 24, 1.);
-                mq::draw_text(text, topleft.0 + rc::scrw() as f32 / 2. - measure.width / 2., topleft.1 + rc::scrh() as f32 / 2. - measure.height / 2., 24., mq::WHITE);
-            } else if nuts_collected == NUTS_GOAL {
-                let text: &str = "All nuts were successfully collected. Press [q] to restart";
-                let measure = mq::measure_text(text, None, 24, 1.);
-                mq::draw_text(text, topleft.0 + rc::scrw() as f32 / 2. - measure.width / 2., topleft.1 +  rc::scrh() as f32 / 2. - measure.height / 2., 24., mq::WHITE);
+            if let Some((text, time)) = &message {
+                if clock::now() - time < MESSAGE_TIME {
+                    let measure = mq::measure_text(text, None, 24, 1.);
+                    mq::draw_text(text, topleft.0 + cx - measure.width / 2., topleft.1 + 120., 24., mq::WHITE);
+                }
+            }
+
+            if health == 0 || clock::now() - last_hurt < 1. {
+                mq::draw_rectangle(topleft.0, topleft.1, rc::scrw() as f32, rc::scrh() as f32, mq::Color::new(1., 0., 0., (1. - (clock::now() - last_hurt)) as f32 * 0.5));
+            }
+
+            if clock::now() - last_teleport < FADE_TIME {
+                mq::draw_rectangle(topleft.0, topleft.1, rc::scrw() as f32, rc::scrh() as f32, mq::Color::new(0.7, 0.95, 1., (1. - (clock::now() - last_teleport) / FADE_TIME) as f32));
+            }
+
+            if health == 0 || nuts_collected == NUTS_GOAL || finished {
+                mq::draw_rectangle(topleft.0, topleft.1, rc::scrw() as f32, rc::scrh() as f32, mq::Color::new(0., 0., 0., 0.5));
             }
 
             mq::next_frame().await;
@@ -0,0 +1,274 @@
+use raycast::prelude as rc;
+use rc::prelude::{macroquad, glam};
+use macroquad::prelude as mq;
+use glam::Vec2;
+use std::cell::RefCell;
+
+/// Turn speed in radians per second with the right stick all the way over
+const LOOK_SPEED: f32 = 3.;
+/// How far the stick has to be pushed to count as held over for look acceleration
+const FULL_TILT: f32 = 0.9;
+/// Seconds at full tilt until look acceleration is at its peak
+const ACCEL_TIME: f32 = 0.6;
+/// Largest dead zone honoured, at 1 there'd be no stick travel left to rescale
+const MAX_DEADZONE: f32 = 0.95;
+
+/// Buttons in the order of the browser's standard gamepad mapping
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Button {
+    South,
+    East,
+    West,
+    North,
+    LeftBumper,
+    RightBumper,
+    LeftTrigger,
+    RightTrigger,
+    Select,
+    Start,
+    LeftStick,
+    RightStick,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+pub const BUTTONS: [Button; 16] = [
+    Button::South,
+    Button::East,
+    Button::West,
+    Button::North,
+    Button::LeftBumper,
+    Button::RightBumper,
+    Button::LeftTrigger,
+    Button::RightTrigger,
+    Button::Select,
+    Button::Start,
+    Button::LeftStick,
+    Button::RightStick,
+    Button::DPadUp,
+    Button::DPadDown,
+    Button::DPadLeft,
+    Button::DPadRight,
+];
+
+/// Snapshot of the first connected gamepad, taken once a frame
+struct State {
+    down: [bool; 16],
+    prev: [bool; 16],
+    /// Sticks with up as positive y
+    left: Vec2,
+    right: Vec2,
+}
+
+thread_local! {
+    static STATE: RefCell<State> = const { RefCell::new(State {
+        down: [false; 16],
+        prev: [false; 16],
+        left: Vec2::ZERO,
+        right: Vec2::ZERO,
+    }) };
+}
+
+/// Reads the gamepad, call once a frame before checking any buttons
+pub fn update() {
+    let (down, left, right): ([bool; 16], Vec2, Vec2) = backend::read();
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        state.prev = state.down;
+        state.down = down;
+        state.left = left;
+        state.right = right;
+    });
+}
+
+pub fn down(button: Button) -> bool {
+    STATE.with(|state| state.borrow().down[button as usize])
+}
+
+/// Went down since the last update
+pub fn pressed(button: Button) -> bool {
+    STATE.with(|state| {
+        let state = state.borrow();
+        state.down[button as usize] && !state.prev[button as usize]
+    })
+}
+
+pub fn released(button: Button) -> bool {
+    STATE.with(|state| {
+        let state = state.borrow();
+        !state.down[button as usize] && state.prev[button as usize]
+    })
+}
+
+/// First button pressed this frame
+pub fn any_pressed() -> Option<Button> {
+    BUTTONS.into_iter().find(|button| pressed(*button))
+}
+
+pub fn left_stick(deadzone: f32) -> Vec2 {
+    apply_deadzone(STATE.with(|state| state.borrow().left), deadzone)
+}
+
+pub fn right_stick(deadzone: f32) -> Vec2 {
+    apply_deadzone(STATE.with(|state| state.borrow().right), deadzone)
+}
+
+/// Zero inside the dead zone, rescaled so the stick still goes smoothly from 0 to 1 outside it
+fn apply_deadzone(stick: Vec2, deadzone: f32) -> Vec2 {
+    let deadzone: f32 = deadzone.clamp(0., MAX_DEADZONE);
+    let len: f32 = stick.length();
+    if len <= deadzone {
+        return Vec2::ZERO;
+    }
+
+    stick / len * ((len - deadzone) / (1. - deadzone)).min(1.)
+}
+
+/// Turning the camera with the right stick
+pub struct Look {
+    /// Seconds the stick has been held at full tilt
+    held: f32,
+}
+
+impl Look {
+    pub fn new() -> Self {
+        Self {
+            held: 0.,
+        }
+    }
+
+    /// `accel` is how much faster than normal turning gets after holding the stick over
+    pub fn update(&mut self, cam: &mut rc::Ray, deadzone: f32, accel: f32) {
+        let dt: f32 = mq::get_frame_time();
+        let x: f32 = right_stick(deadzone).x;
+        if x.abs() > FULL_TILT {
+            self.held = (self.held + dt).min(ACCEL_TIME);
+        } else {
+            self.held = 0.;
+        }
+
+        if x == 0. {
+            return;
+        }
+
+        // Squared response so small tilts aim finely
+        let speed: f32 = LOOK_SPEED * (1. + accel * self.held / ACCEL_TIME);
+        let dir: Vec2 = cam.dir();
+        *cam = rc::Ray::new(cam.orig, dir.y.atan2(dir.x) + x * x.abs() * speed * dt);
+    }
+}
+
+#[cfg(all(not(target_arch = "wasm32"), feature = "gamepad"))]
+mod backend {
+    use super::{Button, BUTTONS, Vec2};
+    use gilrs::Gilrs;
+    use std::cell::RefCell;
+
+    thread_local! {
+        /// `None` when there's no gamepad support on this system
+        static GILRS: RefCell<Option<Gilrs>> = RefCell::new(Gilrs::new().ok());
+    }
+
+    fn button(button: Button) -> gilrs::Button {
+        match button {
+            Button::South => gilrs::Button::South,
+            Button::East => gilrs::Button::East,
+            Button::West => gilrs::Button::West,
+            Button::North => gilrs::Button::North,
+            Button::LeftBumper => gilrs::Button::LeftTrigger,
+            Button::RightBumper => gilrs::Button::RightTrigger,
+            Button::LeftTrigger => gilrs::Button::LeftTrigger2,
+            Button::RightTrigger => gilrs::Button::RightTrigger2,
+            Button::Select => gilrs::Button::Select,
+            Button::Start => gilrs::Button::Start,
+            Button::LeftStick => gilrs::Button::LeftThumb,
+            Button::RightStick => gilrs::Button::RightThumb,
+            Button::DPadUp => gilrs::Button::DPadUp,
+            Button::DPadDown => gilrs::Button::DPadDown,
+            Button::DPadLeft => gilrs::Button::DPadLeft,
+            Button::DPadRight => gilrs::Button::DPadRight,
+        }
+    }
+
+    pub fn read() -> ([bool; 16], Vec2, Vec2) {
+        GILRS.with(|cell| {
+            let mut cell = cell.borrow_mut();
+            let pads: &mut Gilrs = match cell.as_mut() {
+                Some(pads) => pads,
+                None => return ([false; 16], Vec2::ZERO, Vec2::ZERO),
+            };
+
+            // Gamepad state only updates as its events are taken
+            while pads.next_event().is_some() {}
+
+            let pad = match pads.gamepads().next() {
+                Some((_, pad)) => pad,
+                None => return ([false; 16], Vec2::ZERO, Vec2::ZERO),
+            };
+
+            let down: [bool; 16] = BUTTONS.map(|each| pad.is_pressed(button(each)));
+            let left: Vec2 = Vec2::new(pad.value(gilrs::Axis::LeftStickX), pad.value(gilrs::Axis::LeftStickY));
+            let right: Vec2 = Vec2::new(pad.value(gilrs::Axis::RightStickX), pad.value(gilrs::Axis::RightStickY));
+            (down, left, right)
+        })
+    }
+}
+
+/// Native builds without the `gamepad` feature, no gamepad is ever connected
+#[cfg(all(not(target_arch = "wasm32"), not(feature = "gamepad")))]
+mod backend {
+    use super::Vec2;
+
+    pub fn read() -> ([bool; 16], Vec2, Vec2) {
+        ([false; 16], Vec2::ZERO, Vec2::ZERO)
+    }
+}
+
+/// The browser's Gamepad API, through the plugin registered in docs/index.html
+#[cfg(target_arch = "wasm32")]
+mod backend {
+    use super::{BUTTONS, Vec2};
+
+    extern "C" {
+        fn gamepad_button(index: u32) -> u32;
+        fn gamepad_axis(index: u32) -> f32;
+    }
+
+    pub fn read() -> ([bool; 16], Vec2, Vec2) {
+        let down: [bool; 16] = BUTTONS.map(|button| unsafe { gamepad_button(button as u32) } != 0);
+        // The browser has up as negative
+        let left: Vec2 = unsafe { Vec2::new(gamepad_axis(0), -gamepad_axis(1)) };
+        let right: Vec2 = unsafe { Vec2::new(gamepad_axis(2), -gamepad_axis(3)) };
+        (down, left, right)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inside_the_dead_zone_is_still() {
+        assert_eq!(apply_deadzone(Vec2::new(0.1, 0.), 0.15), Vec2::ZERO);
+        assert_eq!(apply_deadzone(Vec2::new(0.15, 0.), 0.15), Vec2::ZERO);
+    }
+
+    #[test]
+    fn outside_rescales_from_zero_to_one() {
+        let half: Vec2 = apply_deadzone(Vec2::new(0., 0.6), 0.2);
+        assert!((half - Vec2::new(0., 0.5)).length() < 1e-6);
+        assert!((apply_deadzone(Vec2::new(1., 0.), 0.2) - Vec2::X).length() < 1e-6);
+        // Diagonals past the rim don't get longer than 1
+        assert!((apply_deadzone(Vec2::new(1., 1.), 0.2).length() - 1.).abs() < 1e-6);
+    }
+
+    #[test]
+    fn full_dead_zone_stays_finite() {
+        let stick: Vec2 = apply_deadzone(Vec2::new(1., 0.), 1.);
+        assert!(stick.is_finite());
+        assert!((stick - Vec2::X).length() < 1e-6);
+        assert!(apply_deadzone(Vec2::new(0.9, 0.), 1.) == Vec2::ZERO);
+    }
+}
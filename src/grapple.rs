@@ -0,0 +1,184 @@
+use crate::clock;
+use raycast::prelude as rc;
+use rc::prelude::glam;
+use glam::Vec2;
+
+pub const GRAPPLE_RANGE: f32 = 500.;
+const GRAPPLE_COOLDOWN: f64 = 0.5;
+const REEL_SPEED: f32 = 3.;
+const PULL: f32 = 0.8;
+const MAX_SPEED: f32 = 20.;
+/// Distance from the anchor at which the hook lets go
+const MIN_LENGTH: f32 = 30.;
+const BEAD_SPACING: f32 = 12.;
+/// Seconds a pull gets to bring its target in before the rope lets go
+const PULL_TIME: f64 = 2.;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum GrappleMode {
+    /// Hook walls and swing from them
+    Swing,
+    /// Hook enemies and pickups and reel them in
+    Pull,
+}
+
+pub struct Grapple {
+    pub mode: GrappleMode,
+    /// Rope path starting at the anchor, the last point is the corner currently swung around
+    points: Vec<Vec2>,
+    /// Rope left between the last point and the player
+    length: f32,
+    last_release: f64,
+}
+
+impl Grapple {
+    pub fn new() -> Self {
+        Self {
+            mode: GrappleMode::Swing,
+            points: Vec::new(),
+            length: 0.,
+            last_release: -100.,
+        }
+    }
+
+    pub fn active(&self) -> bool {
+        !self.points.is_empty()
+    }
+
+    pub fn ready(&self) -> bool {
+        !self.active() && clock::now() - self.last_release >= GRAPPLE_COOLDOWN
+    }
+
+    pub fn toggle_mode(&mut self) {
+        self.mode = match self.mode {
+            GrappleMode::Swing => GrappleMode::Pull,
+            GrappleMode::Pull => GrappleMode::Swing,
+        };
+    }
+
+    pub fn anchor(&self) -> Option<Vec2> {
+        self.points.first().copied()
+    }
+
+    /// Hooks onto `target`, returns false if it is out of range
+    pub fn fire(&mut self, from: Vec2, target: Vec2) -> bool {
+        if from.distance(target) > GRAPPLE_RANGE {
+            return false;
+        }
+
+        self.points = vec![target];
+        self.length = from.distance(target);
+        true
+    }
+
+    pub fn release(&mut self) {
+        self.points.clear();
+        self.last_release = clock::now();
+    }
+
+    /// Swings `pos` along the rope, returns true when the player reaches the anchor
+    pub fn update(&mut self, map: &rc::Map, pos: &mut Vec2, vel: &mut Vec2, input: Vec2) -> bool {
+        self.wrap(map, *pos);
+
+        let pivot: Vec2 = *self.points.last().unwrap();
+        if self.points.len() == 1 && pos.distance(pivot) < MIN_LENGTH {
+            self.release();
+            *vel = Vec2::ZERO;
+            return true;
+        }
+
+        self.length = (self.length - REEL_SPEED).max(0.);
+        *vel += (pivot - *pos).normalize_or_zero() * PULL + input;
+        *vel = vel.clamp_length_max(MAX_SPEED);
+
+        let next: Vec2 = rc::util::move_towards_collidable(map, *pos, *pos + *vel, vel.length());
+        *vel = next - *pos;
+        *pos = next;
+
+        // Taut rope keeps the player on a circle around the pivot
+        let out: Vec2 = *pos - pivot;
+        if out.length() > self.length {
+            let radial: Vec2 = out.normalize_or_zero();
+            *pos = rc::util::move_towards_collidable(map, *pos, pivot, out.length() - self.length);
+            let outward: f32 = vel.dot(radial);
+            if outward > 0. {
+                *vel -= radial * outward;
+            }
+        }
+
+        false
+    }
+
+    /// Rope beads to render from `from` along the rope to the anchor
+    pub fn rope(&self, from: Vec2) -> Vec<rc::Entity> {
+        let mut beads: Vec<rc::Entity> = Vec::new();
+        let mut start: Vec2 = from;
+        for point in self.points.iter().rev() {
+            beads.extend(rope_beads(start, *point));
+            start = *point;
+        }
+
+        beads
+    }
+
+    /// Adds corners the rope got caught on and removes ones it swung free of
+    fn wrap(&mut self, map: &rc::Map, pos: Vec2) {
+        let pivot: Vec2 = *self.points.last().unwrap();
+        if let Some(dist) = blocked(map, pos, pivot) {
+            let corner: Vec2 = pos + (pivot - pos).normalize() * (dist - 1.);
+            self.length = pos.distance(corner);
+            self.points.push(corner);
+        } else if self.points.len() > 1 {
+            let prev: Vec2 = self.points[self.points.len() - 2];
+            if blocked(map, pos, prev).is_none() {
+                self.length += pivot.distance(prev);
+                self.points.pop();
+            }
+        }
+    }
+}
+
+/// Rope beads on a straight line from `from` to `to`
+pub fn rope_beads(from: Vec2, to: Vec2) -> Vec<rc::Entity> {
+    let n: usize = (from.distance(to) / BEAD_SPACING) as usize;
+    (0..n).map(|i| rc::Entity::new(from.lerp(to, i as f32 / n as f32), '~', (3., 3.))).collect()
+}
+
+/// Whether a pull started at `start` has to let go, because it's taken too long, the target is out of range
+/// or a wall came between the player at `from` and the target at `to`
+pub fn pull_broken(map: &rc::Map, start: f64, from: Vec2, to: Vec2) -> bool {
+    pull_expired(start, clock::now(), from, to) || blocked(map, from, to).is_some()
+}
+
+fn pull_expired(start: f64, now: f64, from: Vec2, to: Vec2) -> bool {
+    now - start > PULL_TIME || from.distance(to) > GRAPPLE_RANGE
+}
+
+/// Distance to the wall between `from` and `to`, if there is one
+fn blocked(map: &rc::Map, from: Vec2, to: Vec2) -> Option<f32> {
+    let diff: Vec2 = to - from;
+    let ray: rc::Ray = rc::Ray::new(from, f32::atan2(diff.y, diff.x));
+    let ins: rc::Intersection = rc::cast_ray(map, [].iter(), &[], ray);
+    if ins.distance < diff.length() - 2. {
+        Some(ins.distance)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pulls_let_go_after_a_while() {
+        assert!(!pull_expired(10., 10. + PULL_TIME - 0.1, Vec2::ZERO, Vec2::X));
+        assert!(pull_expired(10., 10. + PULL_TIME + 0.1, Vec2::ZERO, Vec2::X));
+    }
+
+    #[test]
+    fn pulls_let_go_out_of_range() {
+        assert!(!pull_expired(0., 0., Vec2::ZERO, Vec2::new(GRAPPLE_RANGE, 0.)));
+        assert!(pull_expired(0., 0., Vec2::ZERO, Vec2::new(GRAPPLE_RANGE + 1., 0.)));
+    }
+}
@@ -0,0 +1,368 @@
+use crate::gamepad::{self, Button};
+use raycast::prelude as rc;
+use rc::prelude::{macroquad, glam};
+use macroquad::prelude as mq;
+use glam::Vec2;
+use mq::KeyCode;
+use std::collections::HashMap;
+
+/// Everything the player can do with a key or button
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Forward,
+    Back,
+    Left,
+    Right,
+    Sprint,
+    Crouch,
+    Fire,
+    Grapple,
+    GrappleMode,
+    Reload,
+    Use,
+    Weapon1,
+    Weapon2,
+    Weapon3,
+    NextWeapon,
+    PrevWeapon,
+    Map,
+    /// Only while dead, mid-level restarts go through the pause menu so a stray press can't end a run
+    Restart,
+    Pause,
+    Editor,
+}
+
+/// In the order the controls screen lists them
+pub const ACTIONS: [Action; 20] = [
+    Action::Forward,
+    Action::Back,
+    Action::Left,
+    Action::Right,
+    Action::Sprint,
+    Action::Crouch,
+    Action::Fire,
+    Action::Grapple,
+    Action::GrappleMode,
+    Action::Reload,
+    Action::Use,
+    Action::Weapon1,
+    Action::Weapon2,
+    Action::Weapon3,
+    Action::NextWeapon,
+    Action::PrevWeapon,
+    Action::Map,
+    Action::Restart,
+    Action::Pause,
+    Action::Editor,
+];
+
+impl Action {
+    /// Name in the config file
+    pub fn id(&self) -> &'static str {
+        match self {
+            Action::Forward => "forward",
+            Action::Back => "back",
+            Action::Left => "left",
+            Action::Right => "right",
+            Action::Sprint => "sprint",
+            Action::Crouch => "crouch",
+            Action::Fire => "fire",
+            Action::Grapple => "grapple",
+            Action::GrappleMode => "grapple-mode",
+            Action::Reload => "reload",
+            Action::Use => "use",
+            Action::Weapon1 => "weapon-1",
+            Action::Weapon2 => "weapon-2",
+            Action::Weapon3 => "weapon-3",
+            Action::NextWeapon => "next-weapon",
+            Action::PrevWeapon => "prev-weapon",
+            Action::Map => "map",
+            Action::Restart => "restart",
+            Action::Pause => "pause",
+            Action::Editor => "editor",
+        }
+    }
+
+    /// Name on the controls screen
+    pub fn label(&self) -> &'static str {
+        match self {
+            Action::Forward => "Move forward",
+            Action::Back => "Move back",
+            Action::Left => "Strafe left",
+            Action::Right => "Strafe right",
+            Action::Sprint => "Sprint",
+            Action::Crouch => "Crouch",
+            Action::Fire => "Fire",
+            Action::Grapple => "Grapple",
+            Action::GrappleMode => "Grapple mode",
+            Action::Reload => "Reload",
+            Action::Use => "Use",
+            Action::Weapon1 => "Knife",
+            Action::Weapon2 => "Machine gun",
+            Action::Weapon3 => "Gun",
+            Action::NextWeapon => "Next weapon",
+            Action::PrevWeapon => "Previous weapon",
+            Action::Map => "Map",
+            Action::Restart => "Restart after dying",
+            Action::Pause => "Pause",
+            Action::Editor => "Map editor",
+        }
+    }
+
+    fn defaults(&self) -> Vec<Binding> {
+        match self {
+            Action::Forward => vec![Binding::Key(KeyCode::W), Binding::Pad(Button::DPadUp)],
+            Action::Back => vec![Binding::Key(KeyCode::S), Binding::Pad(Button::DPadDown)],
+            Action::Left => vec![Binding::Key(KeyCode::A), Binding::Pad(Button::DPadLeft)],
+            Action::Right => vec![Binding::Key(KeyCode::D), Binding::Pad(Button::DPadRight)],
+            Action::Sprint => vec![Binding::Key(KeyCode::LeftShift), Binding::Pad(Button::LeftStick)],
+            Action::Crouch => vec![Binding::Key(KeyCode::LeftControl), Binding::Key(KeyCode::C), Binding::Pad(Button::East)],
+            Action::Fire => vec![Binding::Mouse(mq::MouseButton::Left), Binding::Pad(Button::RightTrigger)],
+            Action::Grapple => vec![Binding::Mouse(mq::MouseButton::Right), Binding::Pad(Button::LeftTrigger)],
+            Action::GrappleMode => vec![Binding::Key(KeyCode::G), Binding::Pad(Button::North)],
+            Action::Reload => vec![Binding::Key(KeyCode::R), Binding::Pad(Button::West)],
+            Action::Use => vec![Binding::Key(KeyCode::E), Binding::Pad(Button::South)],
+            Action::Weapon1 => vec![Binding::Key(KeyCode::Key1)],
+            Action::Weapon2 => vec![Binding::Key(KeyCode::Key2)],
+            Action::Weapon3 => vec![Binding::Key(KeyCode::Key3)],
+            Action::NextWeapon => vec![Binding::Pad(Button::RightBumper)],
+            Action::PrevWeapon => vec![Binding::Pad(Button::LeftBumper)],
+            Action::Map => vec![Binding::Key(KeyCode::Tab), Binding::Pad(Button::Select)],
+            Action::Restart => vec![Binding::Key(KeyCode::Q)],
+            Action::Pause => vec![Binding::Key(KeyCode::Escape), Binding::Pad(Button::Start)],
+            Action::Editor => vec![Binding::Key(KeyCode::F1)],
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(mq::MouseButton),
+    Pad(Button),
+}
+
+/// Keys that can be bound, their names are what the config file stores
+const KEYS: [KeyCode; 80] = [
+    KeyCode::A, KeyCode::B, KeyCode::C, KeyCode::D, KeyCode::E, KeyCode::F, KeyCode::G, KeyCode::H, KeyCode::I,
+    KeyCode::J, KeyCode::K, KeyCode::L, KeyCode::M, KeyCode::N, KeyCode::O, KeyCode::P, KeyCode::Q, KeyCode::R,
+    KeyCode::S, KeyCode::T, KeyCode::U, KeyCode::V, KeyCode::W, KeyCode::X, KeyCode::Y, KeyCode::Z,
+    KeyCode::Key0, KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4,
+    KeyCode::Key5, KeyCode::Key6, KeyCode::Key7, KeyCode::Key8, KeyCode::Key9,
+    KeyCode::F1, KeyCode::F2, KeyCode::F3, KeyCode::F4, KeyCode::F5, KeyCode::F6,
+    KeyCode::F7, KeyCode::F8, KeyCode::F9, KeyCode::F10, KeyCode::F11, KeyCode::F12,
+    KeyCode::Space, KeyCode::Enter, KeyCode::Tab, KeyCode::Escape, KeyCode::Backspace,
+    KeyCode::Up, KeyCode::Down, KeyCode::Left, KeyCode::Right,
+    KeyCode::LeftShift, KeyCode::RightShift, KeyCode::LeftControl, KeyCode::RightControl, KeyCode::LeftAlt, KeyCode::RightAlt,
+    KeyCode::Comma, KeyCode::Period, KeyCode::Slash, KeyCode::Semicolon, KeyCode::Apostrophe, KeyCode::Minus,
+    KeyCode::Equal, KeyCode::LeftBracket, KeyCode::RightBracket, KeyCode::Backslash, KeyCode::GraveAccent,
+    KeyCode::Insert, KeyCode::Delete, KeyCode::Home, KeyCode::End, KeyCode::PageUp, KeyCode::PageDown,
+];
+
+impl Binding {
+    pub fn name(&self) -> String {
+        match self {
+            Binding::Key(key) => format!("{:?}", key),
+            Binding::Mouse(mq::MouseButton::Left) => String::from("Mouse1"),
+            Binding::Mouse(mq::MouseButton::Right) => String::from("Mouse2"),
+            Binding::Mouse(mq::MouseButton::Middle) => String::from("Mouse3"),
+            Binding::Mouse(button) => format!("{:?}", button),
+            Binding::Pad(button) => format!("Pad{:?}", button),
+        }
+    }
+
+    /// Gamepad and keyboard-and-mouse bindings are kept separately, rebinding one leaves the other
+    fn is_pad(&self) -> bool {
+        matches!(self, Binding::Pad(_))
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "Mouse1" => Some(Binding::Mouse(mq::MouseButton::Left)),
+            "Mouse2" => Some(Binding::Mouse(mq::MouseButton::Right)),
+            "Mouse3" => Some(Binding::Mouse(mq::MouseButton::Middle)),
+            _ if name.starts_with("Pad") => gamepad::BUTTONS.iter().find(|button| format!("Pad{:?}", button) == name).map(|button| Binding::Pad(*button)),
+            _ => KEYS.iter().find(|key| format!("{:?}", key) == name).map(|key| Binding::Key(*key)),
+        }
+    }
+
+    /// Key or button pressed this frame, if it's one that can be bound
+    pub fn pressed_now() -> Option<Self> {
+        if let Some(key) = mq::get_last_key_pressed().filter(|key| KEYS.contains(key)) {
+            return Some(Binding::Key(key));
+        }
+
+        if let Some(button) = gamepad::any_pressed() {
+            return Some(Binding::Pad(button));
+        }
+
+        [mq::MouseButton::Left, mq::MouseButton::Right, mq::MouseButton::Middle].into_iter()
+            .find(|button| mq::is_mouse_button_pressed(*button))
+            .map(Binding::Mouse)
+    }
+
+    fn down(&self) -> bool {
+        match self {
+            Binding::Key(key) => mq::is_key_down(*key),
+            Binding::Mouse(button) => mq::is_mouse_button_down(*button),
+            Binding::Pad(button) => gamepad::down(*button),
+        }
+    }
+
+    fn pressed(&self) -> bool {
+        match self {
+            Binding::Key(key) => mq::is_key_pressed(*key),
+            Binding::Mouse(button) => mq::is_mouse_button_pressed(*button),
+            Binding::Pad(button) => gamepad::pressed(*button),
+        }
+    }
+
+    fn released(&self) -> bool {
+        match self {
+            Binding::Key(key) => mq::is_key_released(*key),
+            Binding::Mouse(button) => mq::is_mouse_button_released(*button),
+            Binding::Pad(button) => gamepad::released(*button),
+        }
+    }
+}
+
+/// What each action is bound to, an action can have several bindings
+pub struct Bindings {
+    map: HashMap<Action, Vec<Binding>>,
+}
+
+impl Bindings {
+    pub fn new() -> Self {
+        Self {
+            map: ACTIONS.iter().map(|action| (*action, action.defaults())).collect(),
+        }
+    }
+
+    pub fn get(&self, action: Action) -> &[Binding] {
+        self.map.get(&action).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// Replaces whatever the action was bound to on the same kind of device
+    pub fn bind(&mut self, action: Action, binding: Binding) {
+        self.replace(action, vec![binding]);
+    }
+
+    fn replace(&mut self, action: Action, bindings: Vec<Binding>) {
+        let current: &mut Vec<Binding> = self.map.entry(action).or_default();
+        current.retain(|old| bindings.iter().all(|new| new.is_pad() != old.is_pad()));
+        current.extend(bindings);
+    }
+
+    pub fn down(&self, action: Action) -> bool {
+        self.get(action).iter().any(Binding::down)
+    }
+
+    pub fn pressed(&self, action: Action) -> bool {
+        self.get(action).iter().any(Binding::pressed)
+    }
+
+    pub fn released(&self, action: Action) -> bool {
+        self.get(action).iter().any(Binding::released)
+    }
+
+    /// Walking direction from the movement actions or the left stick, up to `speed` long and relative to where the camera faces
+    pub fn movement(&self, cam: &rc::Ray, speed: f32, deadzone: f32) -> Vec2 {
+        let forward: Vec2 = cam.dir();
+        // Screen right is the direction of increasing angle
+        let right: Vec2 = Vec2::new(-forward.y, forward.x);
+        let mut wish: Vec2 = Vec2::ZERO;
+        for (action, dir) in [(Action::Forward, forward), (Action::Back, -forward), (Action::Left, -right), (Action::Right, right)] {
+            if self.down(action) {
+                wish += dir;
+            }
+        }
+
+        if wish != Vec2::ZERO {
+            return wish.normalize() * speed;
+        }
+
+        // The stick is analog, pushing it part way walks slower
+        let stick: Vec2 = gamepad::left_stick(deadzone);
+        (forward * stick.y + right * stick.x) * speed
+    }
+
+    /// Loads one `bind.<action> = <binding>, ...` line's action and value, unknown names are skipped
+    pub fn load(&mut self, id: &str, value: &str) {
+        let action: Action = match ACTIONS.iter().find(|action| action.id() == id) {
+            Some(action) => *action,
+            None => return,
+        };
+
+        let bindings: Vec<Binding> = value.split(',').filter_map(|name| Binding::from_name(name.trim())).collect();
+        if !bindings.is_empty() {
+            self.replace(action, bindings);
+        }
+    }
+
+    /// Config file lines for every action
+    pub fn save(&self) -> String {
+        let mut text: String = String::new();
+        for action in ACTIONS {
+            let names: Vec<String> = self.get(action).iter().map(Binding::name).collect();
+            text.push_str(&format!("bind.{} = {}\n", action.id(), names.join(", ")));
+        }
+
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Bindings after going through `save` and `load` on top of the defaults
+    fn round_trip(bindings: &Bindings) -> Bindings {
+        let mut loaded: Bindings = Bindings::new();
+        for line in bindings.save().lines() {
+            let (key, value) = line.split_once('=').unwrap();
+            loaded.load(key.trim().strip_prefix("bind.").unwrap(), value.trim());
+        }
+
+        loaded
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for binding in KEYS.iter().map(|key| Binding::Key(*key))
+                           .chain([mq::MouseButton::Left, mq::MouseButton::Right, mq::MouseButton::Middle].map(Binding::Mouse))
+                           .chain(gamepad::BUTTONS.map(Binding::Pad)) {
+            assert_eq!(Binding::from_name(&binding.name()), Some(binding));
+        }
+    }
+
+    #[test]
+    fn save_load_round_trip() {
+        let mut bindings: Bindings = Bindings::new();
+        bindings.bind(Action::Fire, Binding::Key(KeyCode::F));
+        bindings.bind(Action::Use, Binding::Pad(Button::North));
+        let loaded: Bindings = round_trip(&bindings);
+        for action in ACTIONS {
+            assert_eq!(loaded.get(action), bindings.get(action), "{}", action.id());
+        }
+    }
+
+    #[test]
+    fn rebinding_keeps_the_other_device() {
+        let mut bindings: Bindings = Bindings::new();
+        bindings.bind(Action::Fire, Binding::Key(KeyCode::F));
+        assert_eq!(bindings.get(Action::Fire), &[Binding::Pad(Button::RightTrigger), Binding::Key(KeyCode::F)]);
+
+        bindings.bind(Action::Fire, Binding::Pad(Button::South));
+        assert_eq!(bindings.get(Action::Fire), &[Binding::Key(KeyCode::F), Binding::Pad(Button::South)]);
+    }
+
+    #[test]
+    fn loading_one_device_keeps_the_other() {
+        let mut bindings: Bindings = Bindings::new();
+        bindings.load("fire", "F");
+        assert_eq!(bindings.get(Action::Fire), &[Binding::Pad(Button::RightTrigger), Binding::Key(KeyCode::F)]);
+
+        bindings.load("fire", "nonsense");
+        assert_eq!(bindings.get(Action::Fire), &[Binding::Pad(Button::RightTrigger), Binding::Key(KeyCode::F)]);
+    }
+}
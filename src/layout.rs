@@ -0,0 +1,877 @@
+use crate::clock;
+use raycast::prelude as rc;
+use rc::prelude::{macroquad, glam};
+use macroquad::prelude as mq;
+use glam::IVec2;
+use std::collections::{HashMap, HashSet};
+
+const WALL_HEIGHT: f32 = 2.;
+const DOOR_TIME: f64 = 0.6;
+/// Wall character a door turns into while it sinks open
+///
+/// The engine only knows a height per wall character, so doors sink into the floor
+/// instead of sliding sideways
+const DOOR_FRAME: char = ':';
+
+/// Tiles and sprites with a fixed meaning, they can't be declared as walls
+pub const RESERVED: &str = ".PTRYBSsryb|:%^eEDfFGdchgnamx~HA";
+
+/// Characters a map grid may hold besides its declared walls
+pub const GRID_TILES: &str = ".PRYBTS%^|ryb";
+
+/// Wall textures the map file can pick from
+pub const WALL_TEXTURES: [&str; 6] = ["wall", "brick", "brick-cracked", "brick-broken", "stone", "metal"];
+
+pub fn wall_image(name: &str) -> Option<mq::Image> {
+    let bytes: &[u8] = match name {
+        "wall" => include_bytes!("res/wall.png"),
+        "brick" => include_bytes!("res/brick.png"),
+        "brick-cracked" => include_bytes!("res/brick-cracked.png"),
+        "brick-broken" => include_bytes!("res/brick-broken.png"),
+        "stone" => include_bytes!("res/stone.png"),
+        "metal" => include_bytes!("res/metal.png"),
+        _ => return None,
+    };
+
+    Some(mq::Image::from_file_with_format(bytes, Some(mq::ImageFormat::Png)).unwrap())
+}
+
+/// Floor and ceiling textures the map file can pick from, `sky` only works on ceilings
+pub const SURFACE_TEXTURES: [&str; 8] = ["tiles", "grass", "dirt", "lava", "wood", "acid", "spikes", "sky"];
+
+pub fn surface_image(name: &str) -> Option<mq::Image> {
+    let bytes: &[u8] = match name {
+        "tiles" => include_bytes!("res/tiles.png"),
+        "grass" => include_bytes!("res/grass.png"),
+        "dirt" => include_bytes!("res/dirt.png"),
+        "lava" => include_bytes!("res/lava.png"),
+        "wood" => include_bytes!("res/wood.png"),
+        "acid" => include_bytes!("res/acid.png"),
+        "spikes" => include_bytes!("res/spikes.png"),
+        _ => return None,
+    };
+
+    Some(mq::Image::from_file_with_format(bytes, Some(mq::ImageFormat::Png)).unwrap())
+}
+
+/// Damage per second of a hazard floor tile
+pub fn hazard_damage(tile: char) -> f32 {
+    match tile {
+        '%' => 25.,
+        '^' => 40.,
+        _ => 0.,
+    }
+}
+
+/// Floor texture a hazard tile is drawn with
+pub fn hazard_texture(tile: char) -> Option<&'static str> {
+    match tile {
+        '%' => Some("acid"),
+        '^' => Some("spikes"),
+        _ => None,
+    }
+}
+
+/// Key pickups, each opening the locked door of the same letter in lowercase
+pub const KEYS: [char; 3] = ['R', 'Y', 'B'];
+
+#[derive(Clone)]
+pub enum Action {
+    /// Opens the door at a grid position
+    Open(IVec2),
+    /// Spawns this many enemies
+    Wave(usize),
+    Sound(String),
+    Message(String),
+    /// Ends the level as won
+    End,
+}
+
+/// Area of tiles that runs its actions the first time the player walks into it
+pub struct Trigger {
+    pub min: IVec2,
+    pub max: IVec2,
+    pub actions: Vec<Action>,
+    fired: bool,
+}
+
+/// Wall switch that runs its actions when used
+pub struct Switch {
+    pub pos: IVec2,
+    pub actions: Vec<Action>,
+}
+
+/// Pad that sends whoever steps on it to another tile
+#[derive(Clone, Copy)]
+pub struct Teleporter {
+    pub pos: IVec2,
+    pub dest: IVec2,
+    /// Facing on arrival, in radians
+    pub angle: f32,
+    /// Whether enemies and anything they fling get sent along too
+    pub enemies: bool,
+}
+
+/// Wall character declared by the map file
+pub struct Wall {
+    pub tile: char,
+    pub texture: String,
+    pub height: f32,
+    /// Amplitude and speed of a sine wave added to the height
+    pub wave: Option<(f32, f32)>,
+    /// Tile it turns into after taking this many hits, `.` when destroyed
+    pub breaks: Option<(char, u32)>,
+}
+
+/// Floor or ceiling texture over an area of tiles
+pub struct Region {
+    pub min: IVec2,
+    pub max: IVec2,
+    pub texture: String,
+    /// Damage per second to the player standing on it, only used on floors
+    pub hurt: f32,
+}
+
+impl Region {
+    pub fn contains(&self, pos: IVec2) -> bool {
+        pos.cmpge(self.min).all() && pos.cmple(self.max).all()
+    }
+}
+
+pub enum Directive {
+    Wall(Wall),
+    Floor(Region),
+    Ceiling(Region),
+    Trigger(Trigger),
+    Switch(Switch),
+    Teleporter(Teleporter),
+}
+
+/// What a flood fill through the grid may pass besides floor, it always follows teleporters
+#[derive(Clone, Copy)]
+pub struct Reach {
+    /// Doors, locked or not, as if their keys were already found
+    pub doors: bool,
+    /// Walls that break after enough hits, as if they were already shot through
+    pub breakable: bool,
+}
+
+/// Editable tile grid of a level, built into an `rc::Map` for rendering and collision
+pub struct Layout {
+    pub grid: Vec<Vec<char>>,
+    pub walls: Vec<Wall>,
+    pub floors: Vec<Region>,
+    pub ceilings: Vec<Region>,
+    pub triggers: Vec<Trigger>,
+    pub switches: Vec<Switch>,
+    pub teleporters: Vec<Teleporter>,
+    /// Doors queued to sink open and when each starts, one at a time since they share `DOOR_FRAME`
+    opening: Vec<(IVec2, f64)>,
+    /// Hits taken by breakable walls since their last change
+    damage: HashMap<IVec2, u32>,
+    /// Directive section as written, kept verbatim for saving
+    directives: Vec<String>,
+}
+
+impl Layout {
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let text: String = String::from_utf8_lossy(bytes).into_owned();
+        let mut lines = text.lines().map(|line| line.trim_end_matches('\r'));
+        let grid: Vec<Vec<char>> = lines.by_ref()
+                                        .take_while(|line| !line.is_empty())
+                                        .map(|line| line.chars().collect())
+                                        .collect();
+
+        let mut walls: Vec<Wall> = Vec::new();
+        let mut floors: Vec<Region> = Vec::new();
+        let mut ceilings: Vec<Region> = Vec::new();
+        let mut triggers: Vec<Trigger> = Vec::new();
+        let mut switches: Vec<Switch> = Vec::new();
+        let mut teleporters: Vec<Teleporter> = Vec::new();
+        let directives: Vec<String> = lines.map(String::from).collect();
+        // Broken directives are skipped here, validate-map reports them
+        for line in &directives {
+            match parse_directive(line) {
+                Ok(Some(Directive::Wall(wall))) => walls.push(wall),
+                Ok(Some(Directive::Floor(region))) => floors.push(region),
+                Ok(Some(Directive::Ceiling(region))) => ceilings.push(region),
+                Ok(Some(Directive::Trigger(trigger))) => triggers.push(trigger),
+                Ok(Some(Directive::Switch(switch))) => switches.push(switch),
+                Ok(Some(Directive::Teleporter(teleporter))) => teleporters.push(teleporter),
+                _ => (),
+            }
+        }
+
+        if !walls.iter().any(|wall| wall.tile == '0') {
+            walls.push(Wall {
+                tile: '0',
+                texture: String::from("wall"),
+                height: WALL_HEIGHT,
+                wave: None,
+                breaks: None,
+            });
+        }
+
+        Self {
+            grid,
+            walls,
+            floors,
+            ceilings,
+            triggers,
+            switches,
+            teleporters,
+            opening: Vec::new(),
+            damage: HashMap::new(),
+            directives,
+        }
+    }
+
+    /// Map file text for the grid followed by the original directives
+    pub fn to_text(&self) -> String {
+        let mut text: String = String::new();
+        for row in &self.grid {
+            text.extend(row.iter());
+            text.push('\n');
+        }
+
+        if !self.directives.is_empty() {
+            text.push('\n');
+            for line in &self.directives {
+                text.push_str(line);
+                text.push('\n');
+            }
+        }
+
+        text
+    }
+
+    /// Tile at a grid position, anything outside the grid is wall
+    pub fn at(&self, x: i32, y: i32) -> char {
+        if x < 0 || y < 0 {
+            return '0';
+        }
+
+        self.grid.get(y as usize).and_then(|row| row.get(x as usize)).copied().unwrap_or('0')
+    }
+
+    pub fn find(&self, tile: char) -> Vec<IVec2> {
+        let mut res: Vec<IVec2> = Vec::new();
+        for (y, row) in self.grid.iter().enumerate() {
+            for (x, c) in row.iter().enumerate() {
+                if *c == tile {
+                    res.push(IVec2::new(x as i32, y as i32));
+                }
+            }
+        }
+
+        res
+    }
+
+    /// Floor cells the player can get to from `from`, through doors and teleporters
+    pub fn reachable(&self, from: IVec2) -> Vec<IVec2> {
+        let reach: Reach = Reach {
+            doors: true,
+            breakable: false,
+        };
+
+        self.flood(from, reach).into_iter().filter(|pos| is_floor(self.at(pos.x, pos.y))).collect()
+    }
+
+    /// Whether a flood fill with `reach` can go through `pos`
+    pub fn passable(&self, pos: IVec2, reach: Reach) -> bool {
+        let tile: char = self.at(pos.x, pos.y);
+        if is_floor(tile) {
+            return true;
+        }
+
+        if is_door(tile) || tile == DOOR_FRAME {
+            return reach.doors;
+        }
+
+        reach.breakable && self.walls.iter().any(|wall| wall.tile == tile && wall.breaks.is_some())
+    }
+
+    /// Every cell connected to `from` through cells `reach` can pass
+    pub fn flood(&self, from: IVec2, reach: Reach) -> HashSet<IVec2> {
+        let mut seen: HashSet<IVec2> = HashSet::new();
+        let mut stack: Vec<IVec2> = vec![from];
+        while let Some(pos) = stack.pop() {
+            if !self.passable(pos, reach) || !seen.insert(pos) {
+                continue;
+            }
+
+            stack.extend([pos + IVec2::X, pos - IVec2::X, pos + IVec2::Y, pos - IVec2::Y]);
+            stack.extend(self.teleporters.iter().filter(|teleporter| teleporter.pos == pos).map(|teleporter| teleporter.dest));
+        }
+
+        seen
+    }
+
+    pub fn build(&self, textures: &HashMap<char, mq::Image>) -> rc::Map {
+        let mut bytes: Vec<u8> = Vec::new();
+        for row in &self.grid {
+            bytes.extend(row.iter().map(|tile| map_char(*tile) as u8));
+            bytes.push(b'\n');
+        }
+
+        // Per tile floors and ceilings are applied on top by `Surfaces`
+        let mut map: rc::Map = rc::Map::from_bytes(&bytes, textures.clone());
+        map.floor_tex(rc::Surface::Color(mq::DARKGRAY.into()));
+        map.ceil_tex(rc::Surface::Color(mq::GRAY.into()));
+        for tile in ['|', 'r', 'y', 'b', 'S', 's'] {
+            map.wall_height(tile, WALL_HEIGHT);
+        }
+        map.wall_height(DOOR_FRAME, self.door_height());
+
+        for wall in &self.walls {
+            map.wall_height(wall.tile, wall.height);
+        }
+
+        map
+    }
+
+    /// Moves the heights of walls declared with a wave and of opening doors
+    pub fn animate_walls(&self, map: &mut rc::Map) {
+        map.wall_height(DOOR_FRAME, self.door_height());
+        for wall in &self.walls {
+            if let Some((amplitude, speed)) = wall.wave {
+                map.wall_height(wall.tile, wall.height + amplitude * (clock::now() as f32 * speed).sin());
+            }
+        }
+    }
+
+    /// Floor region covering `pos`, later regions in the file win
+    pub fn floor(&self, pos: IVec2) -> Option<&Region> {
+        self.floors.iter().rev().find(|region| region.contains(pos))
+    }
+
+    pub fn ceiling(&self, pos: IVec2) -> Option<&Region> {
+        self.ceilings.iter().rev().find(|region| region.contains(pos))
+    }
+
+    /// Damage per second dealt to anything standing at `pos`
+    pub fn hurt(&self, pos: IVec2) -> f32 {
+        hazard_damage(self.at(pos.x, pos.y)).max(self.floor(pos).map_or(0., |region| region.hurt))
+    }
+
+    /// Actions of triggers the player at `pos` just walked into
+    pub fn fire_triggers(&mut self, pos: IVec2) -> Vec<Action> {
+        let mut actions: Vec<Action> = Vec::new();
+        for trigger in self.triggers.iter_mut() {
+            if !trigger.fired && pos.cmpge(trigger.min).all() && pos.cmple(trigger.max).all() {
+                trigger.fired = true;
+                actions.extend(trigger.actions.iter().cloned());
+            }
+        }
+
+        actions
+    }
+
+    /// Flips the switch at `pos`, returning its actions if there was an unused one
+    pub fn press_switch(&mut self, pos: IVec2) -> Option<Vec<Action>> {
+        if self.at(pos.x, pos.y) != 'S' {
+            return None;
+        }
+
+        let switch: &Switch = self.switches.iter().find(|switch| switch.pos == pos)?;
+        let actions: Vec<Action> = switch.actions.clone();
+        self.grid[pos.y as usize][pos.x as usize] = 's';
+        Some(actions)
+    }
+
+    pub fn teleporter(&self, pos: IVec2) -> Option<Teleporter> {
+        self.teleporters.iter().find(|teleporter| teleporter.pos == pos).copied()
+    }
+
+    /// Damages a breakable wall, returns true if it changed tiles and the map needs rebuilding
+    pub fn hit_wall(&mut self, pos: IVec2) -> bool {
+        let tile: char = self.at(pos.x, pos.y);
+        let (into, hits) = match self.walls.iter().find(|wall| wall.tile == tile).and_then(|wall| wall.breaks) {
+            Some(breaks) => breaks,
+            None => return false,
+        };
+
+        let taken: &mut u32 = self.damage.entry(pos).or_insert(0);
+        *taken += 1;
+        if *taken < hits {
+            return false;
+        }
+
+        self.damage.remove(&pos);
+        self.grid[pos.y as usize][pos.x as usize] = into;
+        true
+    }
+
+    pub fn open_door(&mut self, pos: IVec2) {
+        if self.opening.iter().any(|(p, _)| *p == pos) {
+            return;
+        }
+
+        let start: f64 = match self.opening.last() {
+            Some((_, last)) => clock::now().max(last + DOOR_TIME),
+            None => clock::now(),
+        };
+        self.opening.push((pos, start));
+    }
+
+    /// Finishes doors that are done sinking, returns true if tiles changed and the map needs rebuilding
+    ///
+    /// Doors only change tile when they start and finish opening, the sinking in between is
+    /// just a wall height so the map isn't rebuilt every frame
+    pub fn update(&mut self) -> bool {
+        let mut changed: bool = false;
+        for (pos, start) in &self.opening {
+            if clock::now() < *start {
+                continue;
+            }
+
+            let tile: char = if clock::now() - start < DOOR_TIME { DOOR_FRAME } else { '.' };
+            let cell: &mut char = &mut self.grid[pos.y as usize][pos.x as usize];
+            if *cell != tile {
+                *cell = tile;
+                changed = true;
+            }
+        }
+
+        let grid: &Vec<Vec<char>> = &self.grid;
+        self.opening.retain(|(pos, _)| grid[pos.y as usize][pos.x as usize] != '.');
+        changed
+    }
+
+    /// Height of the door sinking right now, queued doors wait their turn as closed doors
+    fn door_height(&self) -> f32 {
+        match self.opening.iter().find(|(_, start)| clock::now() >= *start) {
+            Some((_, start)) => WALL_HEIGHT * (1. - ((clock::now() - start) / DOOR_TIME) as f32).max(0.),
+            None => WALL_HEIGHT,
+        }
+    }
+}
+
+pub fn is_door(tile: char) -> bool {
+    tile == '|' || door_key(tile).is_some()
+}
+
+/// Color a tile is drawn with on the editor grid and the automap
+pub fn tile_color(tile: char) -> mq::Color {
+    match tile {
+        '.' => mq::Color::new(0.15, 0.15, 0.15, 1.),
+        'P' => mq::GREEN,
+        '|' | ':' => mq::BROWN,
+        'r' | 'R' => mq::RED,
+        'y' | 'Y' => mq::YELLOW,
+        'b' | 'B' => mq::BLUE,
+        '%' => mq::LIME,
+        '^' => mq::PINK,
+        'S' | 's' => mq::ORANGE,
+        'T' => mq::SKYBLUE,
+        _ => mq::LIGHTGRAY,
+    }
+}
+
+/// Key needed to open a door tile, `None` for unlocked doors
+pub fn door_key(tile: char) -> Option<char> {
+    match tile {
+        'r' => Some('R'),
+        'y' => Some('Y'),
+        'b' => Some('B'),
+        _ => None,
+    }
+}
+
+pub fn key_name(key: char) -> &'static str {
+    match key {
+        'R' => "red",
+        'Y' => "yellow",
+        _ => "blue",
+    }
+}
+
+/// Parses a line of the directive section below the grid, `None` for blank lines and comments
+///
+/// ```text
+/// wall <char> <texture> <height> [wave <amplitude> <speed>] [breaks <char> [hits]]
+/// floor <x> <y> <w> <h> <texture> [hurt <damage per second>]
+/// ceiling <x> <y> <w> <h> <texture>
+/// trigger <x> <y> <w> <h> <action>[; <action>...]
+/// switch <x> <y> <action>[; <action>...]
+/// teleport <x> <y> <dest x> <dest y> <angle in degrees> [enemies]
+/// ```
+///
+/// Actions are `open <x> <y>`, `wave <count>`, `sound <name>`, `message <text>` and `end`.
+pub fn parse_directive(line: &str) -> Result<Option<Directive>, String> {
+    let line: &str = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+
+    let (words, rest) = take_words(line, 1);
+    match words[0] {
+        "wall" => {
+            let (words, rest) = take_words(rest, 3);
+            let tile: char = parse_char(words.first(), "wall character")?;
+            if RESERVED.contains(tile) {
+                return Err(format!("'{}' is reserved and can't be a wall", tile));
+            }
+
+            let texture: &str = words.get(1).copied().unwrap_or("");
+            if !WALL_TEXTURES.contains(&texture) {
+                return Err(format!("unknown wall texture '{}', expected one of {}", texture, WALL_TEXTURES.join(", ")));
+            }
+
+            let height: f32 = parse_num(words.get(2), "height")?;
+            let mut wave: Option<(f32, f32)> = None;
+            let mut breaks: Option<(char, u32)> = None;
+            let words: Vec<&str> = rest.split_whitespace().collect();
+            let mut i: usize = 0;
+            while i < words.len() {
+                match words[i] {
+                    "wave" => {
+                        wave = Some((parse_num(words.get(i + 1), "amplitude")?, parse_num(words.get(i + 2), "speed")?));
+                        i += 3;
+                    }
+                    "breaks" => {
+                        let into: char = parse_char(words.get(i + 1), "broken tile")?;
+                        match words.get(i + 2).and_then(|word| word.parse::<u32>().ok()) {
+                            Some(hits) => {
+                                breaks = Some((into, hits.max(1)));
+                                i += 3;
+                            }
+                            None => {
+                                breaks = Some((into, 1));
+                                i += 2;
+                            }
+                        }
+                    }
+                    word => return Err(format!("unexpected '{}', expected 'wave' or 'breaks'", word)),
+                }
+            }
+
+            Ok(Some(Directive::Wall(Wall {
+                tile,
+                texture: texture.to_string(),
+                height,
+                wave,
+                breaks,
+            })))
+        }
+        kind @ ("floor" | "ceiling") => {
+            let (words, rest) = take_words(rest, 5);
+            let (min, max) = parse_area(&words)?;
+            let texture: &str = words.get(4).copied().unwrap_or("");
+            if !SURFACE_TEXTURES.contains(&texture) || (texture == "sky" && kind == "floor") {
+                return Err(format!("unknown {} texture '{}'", kind, texture));
+            }
+
+            let hurt: f32 = match (kind, take_words(rest, 2).0.as_slice()) {
+                (_, []) => 0.,
+                ("floor", ["hurt", damage]) => parse_num(Some(damage), "damage")?,
+                _ => return Err(String::from("expected 'hurt <damage per second>' on floors only")),
+            };
+
+            let region: Region = Region {
+                min,
+                max,
+                texture: texture.to_string(),
+                hurt,
+            };
+
+            Ok(Some(if kind == "floor" { Directive::Floor(region) } else { Directive::Ceiling(region) }))
+        }
+        "trigger" => {
+            let (words, rest) = take_words(rest, 4);
+            let (min, max) = parse_area(&words)?;
+            Ok(Some(Directive::Trigger(Trigger {
+                min,
+                max,
+                actions: parse_actions(rest)?,
+                fired: false,
+            })))
+        }
+        "switch" => {
+            let (words, rest) = take_words(rest, 2);
+            Ok(Some(Directive::Switch(Switch {
+                pos: IVec2::new(parse_num(words.first(), "x")?, parse_num(words.get(1), "y")?),
+                actions: parse_actions(rest)?,
+            })))
+        }
+        "teleport" => {
+            let (words, rest) = take_words(rest, 5);
+            let enemies: bool = match rest {
+                "" => false,
+                "enemies" => true,
+                _ => return Err(format!("unexpected '{}', expected 'enemies'", rest)),
+            };
+
+            Ok(Some(Directive::Teleporter(Teleporter {
+                pos: IVec2::new(parse_num(words.first(), "x")?, parse_num(words.get(1), "y")?),
+                dest: IVec2::new(parse_num(words.get(2), "destination x")?, parse_num(words.get(3), "destination y")?),
+                angle: parse_num::<f32>(words.get(4), "angle")?.to_radians(),
+                enemies,
+            })))
+        }
+        word => Err(format!("unknown directive '{}'", word)),
+    }
+}
+
+/// Corners of the area given by `<x> <y> <w> <h>` at the start of `words`
+fn parse_area(words: &[&str]) -> Result<(IVec2, IVec2), String> {
+    let x: i32 = parse_num(words.first(), "x")?;
+    let y: i32 = parse_num(words.get(1), "y")?;
+    let w: i32 = parse_num(words.get(2), "width")?;
+    let h: i32 = parse_num(words.get(3), "height")?;
+    if w < 1 || h < 1 {
+        return Err(String::from("area must be at least 1x1"));
+    }
+
+    Ok((IVec2::new(x, y), IVec2::new(x + w - 1, y + h - 1)))
+}
+
+fn parse_actions(text: &str) -> Result<Vec<Action>, String> {
+    if text.is_empty() {
+        return Err(String::from("missing actions"));
+    }
+
+    text.split(';').map(|action| parse_action(action.trim())).collect()
+}
+
+fn parse_action(text: &str) -> Result<Action, String> {
+    let (words, rest) = take_words(text, 1);
+    match words.first().copied().unwrap_or("") {
+        "open" => {
+            let (words, _) = take_words(rest, 2);
+            Ok(Action::Open(IVec2::new(parse_num(words.first(), "x")?, parse_num(words.get(1), "y")?)))
+        }
+        "wave" => Ok(Action::Wave(parse_num(take_words(rest, 1).0.first(), "count")?)),
+        "sound" if !rest.is_empty() => Ok(Action::Sound(rest.to_string())),
+        "message" if !rest.is_empty() => Ok(Action::Message(rest.to_string())),
+        "sound" | "message" => Err(format!("missing text for '{}'", words[0])),
+        "end" => Ok(Action::End),
+        "" => Err(String::from("empty action")),
+        word => Err(format!("unknown action '{}'", word)),
+    }
+}
+
+fn parse_char(word: Option<&&str>, what: &str) -> Result<char, String> {
+    let word: &str = word.ok_or(format!("missing {}", what))?;
+    let mut chars = word.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        _ => Err(format!("{} must be a single character, got '{}'", what, word)),
+    }
+}
+
+fn parse_num<T: std::str::FromStr>(word: Option<&&str>, what: &str) -> Result<T, String> {
+    let word: &str = word.ok_or(format!("missing {}", what))?;
+    word.parse().map_err(|_| format!("invalid {} '{}'", what, word))
+}
+
+/// Splits up to `n` whitespace separated words off the front of `text`, returning them and the rest
+fn take_words(text: &str, n: usize) -> (Vec<&str>, &str) {
+    let mut words: Vec<&str> = Vec::new();
+    let mut rest: &str = text.trim_start();
+    while words.len() < n && !rest.is_empty() {
+        let end: usize = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        words.push(&rest[..end]);
+        rest = rest[end..].trim_start();
+    }
+
+    (words, rest)
+}
+
+/// Character the engine sees for a tile, markers placed on the floor become floor
+fn map_char(tile: char) -> char {
+    match tile {
+        'P' | 'R' | 'Y' | 'B' | 'T' | '%' | '^' => '.',
+        _ => tile,
+    }
+}
+
+/// Whether a tile is walked on like floor, including the markers placed on it
+pub fn is_floor(tile: char) -> bool {
+    map_char(tile) == '.'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blank_lines_and_comments() {
+        assert!(matches!(parse_directive(""), Ok(None)));
+        assert!(matches!(parse_directive("   "), Ok(None)));
+        assert!(matches!(parse_directive("# switch 1 1 end"), Ok(None)));
+    }
+
+    #[test]
+    fn triggers_and_switches() {
+        let trigger: Trigger = match parse_directive("trigger 1 1 2 2 open 3 4; wave 2; sound boom; message hello there; end") {
+            Ok(Some(Directive::Trigger(trigger))) => trigger,
+            _ => panic!("trigger didn't parse"),
+        };
+        assert_eq!((trigger.min, trigger.max), (IVec2::new(1, 1), IVec2::new(2, 2)));
+        assert!(matches!(trigger.actions.as_slice(), [
+            Action::Open(IVec2 { x: 3, y: 4 }),
+            Action::Wave(2),
+            Action::Sound(sound),
+            Action::Message(message),
+            Action::End,
+        ] if sound == "boom" && message == "hello there"));
+
+        let switch: Switch = match parse_directive("switch 5 6 end") {
+            Ok(Some(Directive::Switch(switch))) => switch,
+            _ => panic!("switch didn't parse"),
+        };
+        assert_eq!(switch.pos, IVec2::new(5, 6));
+        assert!(matches!(switch.actions.as_slice(), [Action::End]));
+
+        for line in [
+            "trigger 1 1 2 2",
+            "trigger 1 1 2 2 end;",
+            "trigger 1 1 2 2 dance",
+            "trigger 1 1 2 2 open 3",
+            "trigger 1 1 2 2 wave lots",
+            "trigger 1 1 2 2 sound",
+            "trigger 1 1 0 2 end",
+            "switch 5 end",
+            "switch 5 6",
+        ] {
+            assert!(parse_directive(line).is_err(), "'{}' should fail", line);
+        }
+    }
+
+    #[test]
+    fn unknown_directive() {
+        assert!(parse_directive("door 1 1").is_err());
+    }
+
+    #[test]
+    fn layout_keeps_good_directives() {
+        let layout: Layout = Layout::from_bytes(b"000\n0P0\n000\n\nswitch 1 1 end\nbogus line\ntrigger 1 1 1 1 message hi\n");
+        assert_eq!(layout.grid.len(), 3);
+        assert_eq!((layout.switches.len(), layout.triggers.len()), (1, 1));
+    }
+
+    #[test]
+    fn teleporters() {
+        let teleporter: Teleporter = match parse_directive("teleport 1 2 3 4 90 enemies") {
+            Ok(Some(Directive::Teleporter(teleporter))) => teleporter,
+            _ => panic!("teleporter didn't parse"),
+        };
+        assert_eq!((teleporter.pos, teleporter.dest), (IVec2::new(1, 2), IVec2::new(3, 4)));
+        assert!((teleporter.angle - std::f32::consts::FRAC_PI_2).abs() < 1e-6);
+        assert!(teleporter.enemies);
+
+        assert!(matches!(parse_directive("teleport 1 2 3 4 0"), Ok(Some(Directive::Teleporter(Teleporter { enemies: false, .. })))));
+        for line in ["teleport 1 2 3 4", "teleport 1 2 3 4 0 friends", "teleport a 2 3 4 0"] {
+            assert!(parse_directive(line).is_err(), "'{}' should fail", line);
+        }
+    }
+
+    fn wall(line: &str) -> Wall {
+        match parse_directive(line) {
+            Ok(Some(Directive::Wall(wall))) => wall,
+            _ => panic!("'{}' didn't parse as a wall", line),
+        }
+    }
+
+    #[test]
+    fn walls() {
+        let plain: Wall = wall("wall 1 brick 1.5");
+        assert_eq!((plain.tile, plain.texture.as_str(), plain.height), ('1', "brick", 1.5));
+        assert!(plain.wave.is_none() && plain.breaks.is_none());
+
+        assert_eq!(wall("wall 2 metal 1 wave 0.5 2").wave, Some((0.5, 2.)));
+    }
+
+    #[test]
+    fn malformed_walls() {
+        for line in [
+            "wall",
+            "wall 12 brick 1",
+            "wall 1 marble 1",
+            "wall 1 brick",
+            "wall 1 brick tall",
+            "wall 1 brick 1 wave 0.5",
+            "wall 1 brick 1 glows",
+        ] {
+            assert!(parse_directive(line).is_err(), "'{}' should fail", line);
+        }
+    }
+
+    #[test]
+    fn reserved_chars_are_not_walls() {
+        for tile in RESERVED.chars() {
+            assert!(parse_directive(&format!("wall {} brick 1", tile)).is_err(), "'{}' should be reserved", tile);
+        }
+    }
+
+    #[test]
+    fn declared_walls_and_the_default() {
+        let layout: Layout = Layout::from_bytes(b"000\n0P0\n000\n\nwall 1 brick 1\nwall 1.5 brick 1\n");
+        assert!(layout.walls.iter().any(|wall| wall.tile == '1'));
+        assert!(layout.walls.iter().any(|wall| wall.tile == '0' && wall.texture == "wall"));
+        assert_eq!(layout.walls.len(), 2);
+    }
+
+    fn region(line: &str) -> Region {
+        match parse_directive(line) {
+            Ok(Some(Directive::Floor(region) | Directive::Ceiling(region))) => region,
+            _ => panic!("'{}' didn't parse as a floor or ceiling", line),
+        }
+    }
+
+    #[test]
+    fn floors_and_ceilings() {
+        let floor: Region = region("floor 1 2 3 4 lava hurt 10");
+        assert_eq!((floor.min, floor.max, floor.texture.as_str(), floor.hurt), (IVec2::new(1, 2), IVec2::new(3, 5), "lava", 10.));
+        assert!(floor.contains(IVec2::new(3, 5)) && !floor.contains(IVec2::new(4, 5)));
+
+        let ceiling: Region = region("ceiling 0 0 1 1 sky");
+        assert_eq!((ceiling.texture.as_str(), ceiling.hurt), ("sky", 0.));
+        assert!(matches!(parse_directive("ceiling 0 0 1 1 sky"), Ok(Some(Directive::Ceiling(_)))));
+
+        for line in [
+            "floor 0 0 1 1 sky",
+            "floor 0 0 0 1 tiles",
+            "floor 0 0 1 1",
+            "floor 0 0 1 tiles",
+            "floor 0 0 1 1 tiles hurt",
+            "ceiling 0 0 1 1 tiles hurt 5",
+        ] {
+            assert!(parse_directive(line).is_err(), "'{}' should fail", line);
+        }
+    }
+
+    #[test]
+    fn later_regions_win() {
+        let layout: Layout = Layout::from_bytes(b"0000\n0P.0\n0000\n\nfloor 1 1 2 1 dirt\nfloor 2 1 1 1 lava hurt 10\n");
+        assert_eq!(layout.floor(IVec2::new(1, 1)).map(|region| region.texture.as_str()), Some("dirt"));
+        assert_eq!(layout.floor(IVec2::new(2, 1)).map(|region| region.texture.as_str()), Some("lava"));
+        assert_eq!(layout.hurt(IVec2::new(2, 1)), 10.);
+        assert!(layout.ceiling(IVec2::new(1, 1)).is_none());
+    }
+
+    #[test]
+    fn breakable_walls() {
+        let both: Wall = wall("wall 2 metal 1 wave 0.5 2 breaks 3 4");
+        assert_eq!(both.wave, Some((0.5, 2.)));
+        assert_eq!(both.breaks, Some(('3', 4)));
+
+        assert_eq!(wall("wall 2 stone 1 breaks .").breaks, Some(('.', 1)));
+        assert_eq!(wall("wall 2 stone 1 breaks . 0").breaks, Some(('.', 1)));
+        assert!(parse_directive("wall 1 brick 1 breaks").is_err());
+        assert!(parse_directive("wall 1 brick 1 breaks ..").is_err());
+    }
+
+    #[test]
+    fn walls_break_after_their_hits() {
+        let mut layout: Layout = Layout::from_bytes(b"0000\n0P10\n0000\n\nwall 1 brick 1 breaks . 2\n");
+        let pos: IVec2 = IVec2::new(2, 1);
+        assert!(!layout.hit_wall(pos));
+        assert_eq!(layout.at(2, 1), '1');
+        assert!(layout.hit_wall(pos));
+        assert_eq!(layout.at(2, 1), '.');
+        // Plain walls don't take hits
+        assert!(!layout.hit_wall(IVec2::new(0, 0)));
+    }
+}
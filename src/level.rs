@@ -0,0 +1,71 @@
+/// Chance per frame, in percent, of each spawn happening
+pub struct SpawnRates {
+    pub enemy: f32,
+    pub ammo: f32,
+    pub mg_ammo: f32,
+    pub health: f32,
+    pub armor: f32,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "easy" => Some(Self::Easy),
+            "normal" => Some(Self::Normal),
+            "hard" => Some(Self::Hard),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Easy => "easy",
+            Self::Normal => "normal",
+            Self::Hard => "hard",
+        }
+    }
+
+    /// The one after this on the settings screen, wrapping around
+    pub fn next(&self) -> Self {
+        match self {
+            Self::Easy => Self::Normal,
+            Self::Normal => Self::Hard,
+            Self::Hard => Self::Easy,
+        }
+    }
+
+    /// Whether the objective marker points the way to the nut
+    pub fn compass(&self) -> bool {
+        *self != Self::Hard
+    }
+}
+
+pub struct Level {
+    pub name: &'static str,
+    pub map: &'static [u8],
+    /// Where the editor saves the map, relative to the crate root
+    pub path: &'static str,
+    pub spawns: SpawnRates,
+}
+
+pub const LEVELS: [Level; 1] = [
+    Level {
+        name: "The Swamp",
+        map: include_bytes!("res/map"),
+        path: "src/res/map",
+        spawns: SpawnRates {
+            enemy: 3.,
+            ammo: 1.,
+            mg_ammo: 1.,
+            health: 0.5,
+            armor: 0.3,
+        },
+    },
+];
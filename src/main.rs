@@ -1,25 +1,195 @@
 mod audio;
+mod automap;
+mod clock;
+mod compass;
+mod editor;
 mod game;
+mod gamepad;
+mod grapple;
+mod input;
+mod layout;
+mod level;
+mod mapgen;
+mod menu;
+mod player;
+mod settings;
+mod surface;
+mod validate;
 
-use game::Game;
+use editor::Editor;
+use game::{Exit, Game, Stats};
+use layout::Layout;
+use level::{Difficulty, Level, LEVELS};
+use menu::Menu;
+use settings::Settings;
 use raycast::prelude::macroquad;
 use macroquad::prelude as mq;
 
-#[macroquad::main(window_conf)]
-async fn main() {
-    mq::rand::srand(mq::get_time() as u64);
-    let game: Game = Game::new().await;
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    // Checked before any window opens so it works headless
+    if args.get(1).map(String::as_str) == Some("validate-map") {
+        std::process::exit(validate::run(&args[2..]));
+    }
+
+    let settings: Settings = Settings::load();
+    macroquad::Window::from_config(window_conf(&settings), play(args, settings));
+}
+
+/// Screens of the game's state machine, pausing happens inside `Game::run`
+enum Screen {
+    Title,
+    MainMenu,
+    LevelSelect,
+    Settings,
+    Play,
+    Edit,
+    GameOver(Stats),
+    Victory(Stats),
+}
+
+async fn play(args: Vec<String>, mut settings: Settings) {
+    // `--seed n` replays a run, `--endless` generates a fresh map for every run
+    let mut seed: u64 = args.iter()
+                            .position(|arg| arg == "--seed")
+                            .and_then(|i| args.get(i + 1))
+                            .and_then(|arg| arg.parse().ok())
+                            .unwrap_or(macroquad::miniquad::date::now() as u64);
+    let mut endless: bool = args.iter().any(|arg| arg == "--endless");
+    if let Some(difficulty) = args.iter()
+                                  .position(|arg| arg == "--difficulty")
+                                  .and_then(|i| args.get(i + 1))
+                                  .and_then(|arg| Difficulty::from_name(arg)) {
+        settings.difficulty = difficulty;
+    }
+    mq::rand::srand(seed);
+
+    let mut level: usize = 0;
+    let (mut path, mut source): (String, Vec<u8>) = if endless { endless_map(seed) } else { level_map(&LEVELS[level]) };
+    // The flags skip the menus and go straight to playing or editing
+    let mut screen: Screen = if endless { Screen::Play } else { Screen::Title };
+
+    // `--edit [path]` starts in the editor, on the given file if it exists
+    if let Some(i) = args.iter().position(|arg| arg == "--edit") {
+        screen = Screen::Edit;
+        if let Some(file) = args.get(i + 1).filter(|arg| !arg.starts_with("--")) {
+            path = file.clone();
+            if let Ok(bytes) = std::fs::read(&path) {
+                source = bytes;
+            }
+        }
+    }
+
+    let game: Game = Game::new(&settings).await;
 
     loop {
-        game.run().await;
+        screen = match screen {
+            Screen::Title => {
+                menu::title().await;
+                Screen::MainMenu
+            },
+            Screen::MainMenu => {
+                let mut items: Vec<String> = ["Play", "Endless", "Level select", "Settings"].map(String::from).to_vec();
+                // There's no quitting a browser tab from inside it
+                if !cfg!(target_arch = "wasm32") {
+                    items.push(String::from("Quit"));
+                }
+
+                match Menu::new().choose(menu::TITLE, &[], &items, None).await {
+                    Some(0) => {
+                        endless = false;
+                        level = 0;
+                        (path, source) = level_map(&LEVELS[level]);
+                        Screen::Play
+                    },
+                    Some(1) => {
+                        endless = true;
+                        level = 0;
+                        seed = seed.wrapping_add(1);
+                        (path, source) = endless_map(seed);
+                        Screen::Play
+                    },
+                    Some(2) => Screen::LevelSelect,
+                    Some(3) => Screen::Settings,
+                    // Returning ends the main future, macroquad then closes the window itself
+                    Some(_) => return,
+                    None => Screen::Title,
+                }
+            },
+            Screen::LevelSelect => {
+                let items: Vec<String> = LEVELS.iter().map(|level| String::from(level.name)).collect();
+                match Menu::new().choose("LEVEL SELECT", &[], &items, None).await {
+                    Some(i) => {
+                        endless = false;
+                        level = i;
+                        (path, source) = level_map(&LEVELS[level]);
+                        Screen::Play
+                    },
+                    None => Screen::MainMenu,
+                }
+            },
+            Screen::Settings => {
+                menu::settings(&mut settings, None).await;
+                game.set_volume(settings.volume);
+                Screen::MainMenu
+            },
+            Screen::Edit => {
+                let mut layout: Layout = Layout::from_bytes(&source);
+                Editor::new(&path, &layout).run(&mut layout, &settings).await;
+                source = layout.to_text().into_bytes();
+                Screen::Play
+            },
+            Screen::Play => match game.run(&LEVELS[level], Layout::from_bytes(&source), &mut settings).await {
+                // Restarting replays the same map, only winning moves endless mode to a new seed
+                Exit::Restart => Screen::Play,
+                Exit::Edit => Screen::Edit,
+                Exit::Quit => Screen::MainMenu,
+                Exit::Dead(stats) => Screen::GameOver(stats),
+                Exit::Won(stats) => Screen::Victory(stats),
+            },
+            Screen::GameOver(stats) => {
+                let items: [String; 2] = ["Retry", "Main menu"].map(String::from);
+                match Menu::new().choose("GAME OVER", &menu::stats_lines(&stats), &items, None).await {
+                    Some(0) => Screen::Play,
+                    _ => Screen::MainMenu,
+                }
+            },
+            Screen::Victory(stats) => {
+                let next: bool = endless || level + 1 < LEVELS.len();
+                let items: [String; 2] = [if next { "Next level" } else { "Play again" }, "Main menu"].map(String::from);
+                match Menu::new().choose("VICTORY", &menu::stats_lines(&stats), &items, None).await {
+                    Some(0) => {
+                        if endless {
+                            seed = seed.wrapping_add(1);
+                            (path, source) = endless_map(seed);
+                        } else if next {
+                            level += 1;
+                            (path, source) = level_map(&LEVELS[level]);
+                        }
+                        Screen::Play
+                    },
+                    _ => Screen::MainMenu,
+                }
+            },
+        };
     }
 }
 
-fn window_conf() -> mq::Conf {
+/// Save path and map text for a built-in level
+fn level_map(level: &Level) -> (String, Vec<u8>) {
+    (String::from(level.path), level.map.to_vec())
+}
+
+/// Save path and map text for a generated map
+fn endless_map(seed: u64) -> (String, Vec<u8>) {
+    (format!("seed-{}.map", seed), mapgen::generate(seed).into_bytes())
+}
+
+fn window_conf(settings: &Settings) -> mq::Conf {
     mq::Conf {
         window_title: String::from("raycast"),
-        window_width: 800,
-        window_height: 800,
+        window_width: settings.resolution.0,
+        window_height: settings.resolution.1,
         window_resizable: false,
         ..Default::default()
     }
@@ -0,0 +1,201 @@
+use raycast::prelude as rc;
+use rc::prelude::glam;
+use glam::IVec2;
+
+const MAP_SIZE: i32 = 32;
+const MAX_ROOMS: usize = 10;
+const ROOM_TRIES: usize = 200;
+const MIN_ROOM: i32 = 3;
+const MAX_ROOM: i32 = 8;
+/// Wall characters the interior walls are built from, the outer wall is always '0'
+const INNER_WALLS: [char; 3] = ['0', '1', '2'];
+const WALL_DIRECTIVES: [&str; 3] = ["wall 0 wall 2", "wall 1 brick 2", "wall 2 stone 3"];
+/// Floors a room can be dressed with, grass rooms are open to the sky
+const ROOM_FLOORS: [&str; 4] = ["tiles", "wood", "dirt", "grass"];
+
+/// Xorshift generator of its own, so generating a map leaves the game's random numbers alone
+struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // Xorshift never leaves a zero state, so mix the seed up first
+        Self {
+            state: (seed ^ 0x9e37_79b9_7f4a_7c15).max(1),
+        }
+    }
+
+    fn next(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    /// Random number in `low..high`
+    fn roll(&mut self, low: i32, high: i32) -> i32 {
+        low + (self.next() % (high - low).max(1) as u64) as i32
+    }
+
+    fn pick<T: Copy>(&mut self, options: &[T]) -> T {
+        options[self.roll(0, options.len() as i32) as usize]
+    }
+}
+
+struct Room {
+    min: IVec2,
+    size: IVec2,
+}
+
+impl Room {
+    fn center(&self) -> IVec2 {
+        self.min + self.size / 2
+    }
+
+    /// Overlapping or touching, rooms keep at least one wall between them
+    fn overlaps(&self, other: &Room) -> bool {
+        self.min.x <= other.min.x + other.size.x && other.min.x <= self.min.x + self.size.x &&
+        self.min.y <= other.min.y + other.size.y && other.min.y <= self.min.y + self.size.y
+    }
+}
+
+/// Map text for a rooms-and-corridors level, the same seed always gives the same map.
+/// Every room is joined to the one placed before it, so all floor is connected.
+pub fn generate(seed: u64) -> String {
+    let mut rng: Rng = Rng::new(seed);
+
+    let inner: char = rng.pick(&INNER_WALLS);
+    let mut grid: Vec<Vec<char>> = vec![vec![inner; MAP_SIZE as usize]; MAP_SIZE as usize];
+    let last: usize = MAP_SIZE as usize - 1;
+    for (y, row) in grid.iter_mut().enumerate() {
+        for (x, tile) in row.iter_mut().enumerate() {
+            if x == 0 || y == 0 || x == last || y == last {
+                *tile = '0';
+            }
+        }
+    }
+
+    let mut rooms: Vec<Room> = Vec::new();
+    for _ in 0..ROOM_TRIES {
+        if rooms.len() == MAX_ROOMS {
+            break;
+        }
+
+        let size: IVec2 = IVec2::new(rng.roll(MIN_ROOM, MAX_ROOM + 1), rng.roll(MIN_ROOM, MAX_ROOM + 1));
+        let min: IVec2 = IVec2::new(rng.roll(1, MAP_SIZE - 1 - size.x), rng.roll(1, MAP_SIZE - 1 - size.y));
+        let room: Room = Room { min, size };
+        if !rooms.iter().any(|other| other.overlaps(&room)) {
+            rooms.push(room);
+        }
+    }
+
+    for room in &rooms {
+        for y in room.min.y..room.min.y + room.size.y {
+            for x in room.min.x..room.min.x + room.size.x {
+                grid[y as usize][x as usize] = '.';
+            }
+        }
+    }
+
+    for pair in rooms.windows(2) {
+        let from: IVec2 = pair[0].center();
+        let to: IVec2 = pair[1].center();
+        // Bend the corridor either way round so they don't all look alike
+        let corner: IVec2 = if rng.roll(0, 2) == 0 { IVec2::new(to.x, from.y) } else { IVec2::new(from.x, to.y) };
+        carve(&mut grid, from, corner);
+        carve(&mut grid, corner, to);
+    }
+
+    let start: IVec2 = rooms[0].center();
+    grid[start.y as usize][start.x as usize] = 'P';
+
+    let mut text: String = String::new();
+    for row in &grid {
+        text.extend(row.iter());
+        text.push('\n');
+    }
+
+    text.push('\n');
+    text.push_str(&format!("# generated from seed {}\n", seed));
+    for line in WALL_DIRECTIVES {
+        text.push_str(line);
+        text.push('\n');
+    }
+    for room in &rooms {
+        let floor: &str = rng.pick(&ROOM_FLOORS);
+        text.push_str(&format!("floor {} {} {} {} {}\n", room.min.x, room.min.y, room.size.x, room.size.y, floor));
+        if floor == "grass" {
+            text.push_str(&format!("ceiling {} {} {} {} sky\n", room.min.x, room.min.y, room.size.x, room.size.y));
+        }
+    }
+
+    text
+}
+
+/// Straight line of floor between two cells on the same row or column
+fn carve(grid: &mut [Vec<char>], from: IVec2, to: IVec2) {
+    let step: IVec2 = (to - from).signum();
+    let mut pos: IVec2 = from;
+    loop {
+        grid[pos.y as usize][pos.x as usize] = '.';
+        if pos == to {
+            break;
+        }
+        pos += step;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::Layout;
+
+    #[test]
+    fn same_seed_same_map() {
+        assert_eq!(generate(42), generate(42));
+        assert_ne!(generate(42), generate(43));
+    }
+
+    #[test]
+    fn seed_zero_still_varies() {
+        let mut rng: Rng = Rng::new(0);
+        let rolls: Vec<i32> = (0..8).map(|_| rng.roll(0, 100)).collect();
+        assert!(rolls.iter().any(|roll| *roll != rolls[0]));
+        assert!(rolls.iter().all(|roll| (0..100).contains(roll)));
+    }
+
+    #[test]
+    fn one_start_inside_the_outer_wall() {
+        let map: String = generate(7);
+        let rows: Vec<&str> = map.lines().take_while(|line| !line.is_empty()).collect();
+        assert_eq!(rows.len(), MAP_SIZE as usize);
+        assert_eq!(map.matches('P').count(), 1);
+        assert!(rows[0].chars().chain(rows[rows.len() - 1].chars()).all(|tile| tile == '0'));
+        assert!(rows.iter().all(|row| row.starts_with('0') && row.ends_with('0')));
+    }
+
+    #[test]
+    fn all_floor_reachable_from_the_start() {
+        for seed in [0, 1, 7, 42, 1000, 123456789] {
+            let layout: Layout = Layout::from_bytes(generate(seed).as_bytes());
+            let mut floor: Vec<IVec2> = Vec::new();
+            let mut start: Option<IVec2> = None;
+            for (y, row) in layout.grid.iter().enumerate() {
+                for (x, tile) in row.iter().enumerate() {
+                    let pos: IVec2 = IVec2::new(x as i32, y as i32);
+                    if *tile == 'P' {
+                        start = Some(pos);
+                    }
+                    if *tile == '.' || *tile == 'P' {
+                        floor.push(pos);
+                    }
+                }
+            }
+
+            let mut reached: Vec<IVec2> = layout.reachable(start.unwrap());
+            reached.sort_by_key(|pos| (pos.y, pos.x));
+            assert_eq!(reached, floor, "seed {}", seed);
+        }
+    }
+}
@@ -0,0 +1,256 @@
+use crate::game::Stats;
+use crate::gamepad::{self, Button};
+use crate::input::{self, Binding, Bindings};
+use crate::settings::{self, Settings};
+use raycast::prelude as rc;
+use rc::prelude::macroquad;
+use macroquad::prelude as mq;
+
+pub const TITLE: &str = "RAYCAST";
+const TITLE_SIZE: f32 = 64.;
+const LINE_SIZE: f32 = 24.;
+const ITEM_SIZE: f32 = 32.;
+const ITEM_SPACING: f32 = 48.;
+/// Padding around an item's text that still counts as clicking it
+const ITEM_PADDING: f32 = 10.;
+
+/// Vertical list of options picked with the mouse, the arrow keys and enter or a gamepad's d-pad
+pub struct Menu {
+    selected: usize,
+    last_mouse: (f32, f32),
+}
+
+impl Menu {
+    pub fn new() -> Self {
+        Self {
+            selected: 0,
+            last_mouse: mq::mouse_position(),
+        }
+    }
+
+    /// Shows the menu until an item is picked, `None` when it's backed out of with escape.
+    /// `background` is drawn dimmed behind it, like the frozen game when paused.
+    pub async fn choose(&mut self, title: &str, lines: &[String], items: &[String], background: Option<&mq::Texture2D>) -> Option<usize> {
+        mq::set_cursor_grab(false);
+        mq::show_mouse(true);
+        self.selected = self.selected.min(items.len().saturating_sub(1));
+
+        loop {
+            let rects: Vec<mq::Rect> = self.draw(title, lines, items, background);
+            // Input is read after the frame so the key that opened the menu doesn't also pick in it
+            mq::next_frame().await;
+            gamepad::update();
+
+            if let Some(res) = self.input(&rects) {
+                // And one more frame so the pick doesn't carry over into whatever comes next
+                self.draw(title, lines, items, background);
+                mq::next_frame().await;
+                return res;
+            }
+        }
+    }
+
+    /// `Some` with the outcome once something's picked or escape is pressed
+    fn input(&mut self, rects: &[mq::Rect]) -> Option<Option<usize>> {
+        if mq::is_key_pressed(mq::KeyCode::Escape) || gamepad::pressed(Button::East) || gamepad::pressed(Button::Start) {
+            return Some(None);
+        }
+        if mq::is_key_pressed(mq::KeyCode::Up) || mq::is_key_pressed(mq::KeyCode::W) || gamepad::pressed(Button::DPadUp) {
+            self.selected = (self.selected + rects.len() - 1) % rects.len();
+        }
+        if mq::is_key_pressed(mq::KeyCode::Down) || mq::is_key_pressed(mq::KeyCode::S) || gamepad::pressed(Button::DPadDown) {
+            self.selected = (self.selected + 1) % rects.len();
+        }
+        if mq::is_key_pressed(mq::KeyCode::Enter) || mq::is_key_pressed(mq::KeyCode::Space) || gamepad::pressed(Button::South) {
+            return Some(Some(self.selected));
+        }
+
+        let mouse: (f32, f32) = mq::mouse_position();
+        let hovered: Option<usize> = rects.iter().position(|rect| rect.contains(mq::vec2(mouse.0, mouse.1)));
+        // Only follow the mouse when it moves so it doesn't fight the keyboard
+        if mouse != self.last_mouse {
+            self.last_mouse = mouse;
+            if let Some(i) = hovered {
+                self.selected = i;
+            }
+        }
+
+        hovered.filter(|_| mq::is_mouse_button_pressed(mq::MouseButton::Left)).map(Some)
+    }
+
+    /// Draws a frame of the menu and returns where each item was drawn
+    fn draw(&self, title: &str, lines: &[String], items: &[String], background: Option<&mq::Texture2D>) -> Vec<mq::Rect> {
+        let w: f32 = mq::screen_width();
+        let h: f32 = mq::screen_height();
+        draw_background(background);
+
+        let mut y: f32 = h * 0.25;
+        draw_centered(title, y, TITLE_SIZE, mq::WHITE);
+        y += TITLE_SIZE;
+        for line in lines {
+            draw_centered(line, y, LINE_SIZE, mq::LIGHTGRAY);
+            y += LINE_SIZE * 1.25;
+        }
+
+        // Long lists start higher and get squeezed together to fit on screen
+        y = y.max((h * 0.5).min(h - items.len() as f32 * ITEM_SPACING));
+        let spacing: f32 = ITEM_SPACING.min((h - y) / items.len().max(1) as f32);
+        let size: f32 = ITEM_SIZE * spacing / ITEM_SPACING;
+        let mut rects: Vec<mq::Rect> = Vec::new();
+        for (i, item) in items.iter().enumerate() {
+            let color: mq::Color = if i == self.selected { mq::YELLOW } else { mq::WHITE };
+            let width: f32 = draw_centered(item, y, size, color);
+            rects.push(mq::Rect::new((w - width) / 2. - ITEM_PADDING, y - size, width + ITEM_PADDING * 2., size + ITEM_PADDING));
+            y += spacing;
+        }
+
+        rects
+    }
+}
+
+/// Clears the screen and draws `background` dimmed
+fn draw_background(background: Option<&mq::Texture2D>) {
+    mq::clear_background(mq::BLACK);
+    if let Some(texture) = background {
+        let topleft: (f32, f32) = rc::scr_topleft();
+        mq::draw_texture(texture, topleft.0, topleft.1, mq::WHITE);
+        mq::draw_rectangle(0., 0., mq::screen_width(), mq::screen_height(), mq::Color::new(0., 0., 0., 0.6));
+    }
+}
+
+/// Draws text centered on the screen horizontally, returns its width
+fn draw_centered(text: &str, y: f32, size: f32, color: mq::Color) -> f32 {
+    let measure = mq::measure_text(text, None, size as u16, 1.);
+    mq::draw_text(text, (mq::screen_width() - measure.width) / 2., y, size, color);
+    measure.width
+}
+
+/// Waits for any key or click
+pub async fn title() {
+    mq::set_cursor_grab(false);
+    mq::show_mouse(true);
+
+    loop {
+        mq::clear_background(mq::BLACK);
+        draw_centered(TITLE, mq::screen_height() * 0.4, TITLE_SIZE, mq::WHITE);
+        // Blink the prompt
+        if mq::get_time() % 1. < 0.7 {
+            draw_centered("press any key", mq::screen_height() * 0.6, LINE_SIZE, mq::LIGHTGRAY);
+        }
+        mq::next_frame().await;
+        gamepad::update();
+
+        if mq::get_last_key_pressed().is_some() || mq::is_mouse_button_pressed(mq::MouseButton::Left) || gamepad::any_pressed().is_some() {
+            return;
+        }
+    }
+}
+
+/// Settings screen, picking an option moves it to its next value. Saved on the way out.
+pub async fn settings(settings: &mut Settings, background: Option<&mq::Texture2D>) {
+    let mut menu: Menu = Menu::new();
+    loop {
+        let items: [String; 10] = [
+            format!("Difficulty: {}", settings.difficulty.name()),
+            format!("Mouse sensitivity: {}", settings.sensitivity),
+            format!("Volume: {}%", (settings.volume * 100.) as i32),
+            format!("Resolution: {}x{}", settings.resolution.0, settings.resolution.1),
+            format!("Screen shake: {}%", (settings.shake * 100.) as i32),
+            format!("Corpses: {}", settings.corpses),
+            format!("Stick dead zone: {}%", (settings.deadzone * 100.) as i32),
+            format!("Look acceleration: {}%", (settings.look_accel * 100.) as i32),
+            String::from("Controls"),
+            String::from("Back"),
+        ];
+        match menu.choose("SETTINGS", &[], &items, background).await {
+            Some(0) => settings.difficulty = settings.difficulty.next(),
+            Some(1) => settings.sensitivity = settings::cycle(&settings::SENSITIVITIES, settings.sensitivity),
+            Some(2) => settings.volume = settings::cycle(&settings::VOLUMES, settings.volume),
+            Some(3) => {
+                settings.resolution = settings::cycle(&settings::RESOLUTIONS, settings.resolution);
+                mq::request_new_screen_size(settings.resolution.0 as f32, settings.resolution.1 as f32);
+            },
+            Some(4) => settings.shake = settings::cycle(&settings::SHAKES, settings.shake),
+            Some(5) => settings.corpses = settings::cycle(&settings::CORPSE_LIMITS, settings.corpses),
+            Some(6) => settings.deadzone = settings::cycle(&settings::DEADZONES, settings.deadzone),
+            Some(7) => settings.look_accel = settings::cycle(&settings::LOOK_ACCELS, settings.look_accel),
+            Some(8) => controls(&mut settings.bindings, background).await,
+            _ => {
+                settings.save();
+                return;
+            },
+        }
+    }
+}
+
+/// Lists every action with what it's bound to, picking one rebinds it to the next key or button pressed
+async fn controls(bindings: &mut Bindings, background: Option<&mq::Texture2D>) {
+    let mut menu: Menu = Menu::new();
+    loop {
+        let mut items: Vec<String> = input::ACTIONS.iter().map(|action| {
+            let names: Vec<String> = bindings.get(*action).iter().map(Binding::name).collect();
+            format!("{}: {}", action.label(), names.join(", "))
+        }).collect();
+        items.push(String::from("Reset to defaults"));
+        items.push(String::from("Back"));
+
+        match menu.choose("CONTROLS", &[], &items, background).await {
+            Some(i) if i < input::ACTIONS.len() => {
+                let action: input::Action = input::ACTIONS[i];
+                if let Some(binding) = wait_for_binding(action.label(), background).await {
+                    bindings.bind(action, binding);
+                }
+            },
+            Some(i) if i == input::ACTIONS.len() => *bindings = Bindings::new(),
+            _ => return,
+        }
+    }
+}
+
+/// Next key or mouse button pressed, `None` when escape cancels
+async fn wait_for_binding(label: &str, background: Option<&mq::Texture2D>) -> Option<Binding> {
+    loop {
+        draw_background(background);
+        draw_centered(label, mq::screen_height() * 0.4, TITLE_SIZE, mq::WHITE);
+        draw_centered("press a key, mouse or gamepad button, escape cancels", mq::screen_height() * 0.6, LINE_SIZE, mq::LIGHTGRAY);
+        mq::next_frame().await;
+        gamepad::update();
+
+        if mq::is_key_pressed(mq::KeyCode::Escape) {
+            return None;
+        }
+        if let Some(binding) = Binding::pressed_now() {
+            return Some(binding);
+        }
+    }
+}
+
+/// Lines summing up a run for the game over and victory screens
+pub fn stats_lines(stats: &Stats) -> Vec<String> {
+    let time: u32 = stats.time as u32;
+    vec![
+        format!("Time: {}:{:02}", time / 60, time % 60),
+        format!("Kills: {}", stats.kills),
+        format!("Nuts: {}", stats.nuts),
+    ]
+}
+
+pub enum PauseChoice {
+    Resume,
+    Restart,
+    Quit,
+}
+
+/// Pause menu over the frozen game
+pub async fn pause(settings: &mut Settings, background: &mq::Texture2D) -> PauseChoice {
+    let mut menu: Menu = Menu::new();
+    let items: [String; 4] = ["Resume", "Restart", "Settings", "Main menu"].map(String::from);
+    loop {
+        match menu.choose("PAUSED", &[], &items, Some(background)).await {
+            None | Some(0) => return PauseChoice::Resume,
+            Some(1) => return PauseChoice::Restart,
+            Some(2) => self::settings(settings, Some(background)).await,
+            _ => return PauseChoice::Quit,
+        }
+    }
+}
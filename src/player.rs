@@ -0,0 +1,157 @@
+use raycast::prelude as rc;
+use rc::prelude::{macroquad, glam};
+use macroquad::prelude as mq;
+use glam::Vec2;
+
+const BASE_SPEED: f32 = 4.;
+const SPRINT_MULTIPLIER: f32 = 1.6;
+const CROUCH_MULTIPLIER: f32 = 0.5;
+
+/// Speed multiplier for carrying each item, indexed like the item list
+const ITEM_WEIGHTS: [f32; 3] = [1., 0.5, 0.75];
+
+/// Fraction of the gap to the wanted velocity closed per frame
+const ACCELERATION: f32 = 0.25;
+const FRICTION: f32 = 0.7;
+/// Friction applied while moving faster than running speed, so knockback and swings carry
+const SLIDE_FRICTION: f32 = 0.94;
+const SLIDE_STEER: f32 = 0.05;
+
+const BLAST_RADIUS: f32 = 80.;
+const BLAST_FORCE: f32 = 10.;
+
+const MAX_STAMINA: f32 = 1.;
+const STAMINA_DRAIN: f32 = 0.35;
+const STAMINA_REGEN: f32 = 0.2;
+/// Stamina needed before sprinting again after running out
+const STAMINA_RECOVER: f32 = 0.3;
+
+const CROUCH_DEPTH: f32 = 60.;
+const CROUCH_RATE: f32 = 12.;
+
+const BOB_FREQ: f32 = 0.08;
+const BOB_WALK: f32 = 4.;
+const BOB_RUN: f32 = 8.;
+
+pub struct Movement {
+    pub velocity: Vec2,
+    stamina: f32,
+    exhausted: bool,
+    sprinting: bool,
+    crouching: bool,
+    crouch: f32,
+    bob_phase: f32,
+    bob_amp: f32,
+}
+
+impl Movement {
+    pub fn new() -> Self {
+        Self {
+            velocity: Vec2::ZERO,
+            stamina: MAX_STAMINA,
+            exhausted: false,
+            sprinting: false,
+            crouching: false,
+            crouch: 0.,
+            bob_phase: 0.,
+            bob_amp: 0.,
+        }
+    }
+
+    pub fn update(&mut self, moving: bool, sprint: bool, crouch: bool, item: usize) {
+        let dt: f32 = mq::get_frame_time();
+
+        self.crouching = crouch;
+        self.sprinting = sprint && moving && !crouch && !self.exhausted;
+
+        if self.sprinting {
+            self.stamina -= STAMINA_DRAIN * dt;
+            if self.stamina <= 0. {
+                self.stamina = 0.;
+                self.exhausted = true;
+            }
+        } else {
+            self.stamina = (self.stamina + STAMINA_REGEN * dt).min(MAX_STAMINA);
+            if self.exhausted && self.stamina >= STAMINA_RECOVER {
+                self.exhausted = false;
+            }
+        }
+
+        let crouch_target: f32 = if crouch { 1. } else { 0. };
+        self.crouch += (crouch_target - self.crouch) * (CROUCH_RATE * dt).min(1.);
+
+        if moving {
+            self.bob_phase += self.speed(item) * BOB_FREQ;
+        }
+
+        let bob_target: f32 = if !moving {
+            0.
+        } else if self.sprinting {
+            BOB_RUN
+        } else if self.crouching {
+            BOB_WALK / 2.
+        } else {
+            BOB_WALK
+        };
+        self.bob_amp += (bob_target - self.bob_amp) * (10. * dt).min(1.);
+    }
+
+    /// Accelerates towards `wish` direction and moves `pos` by the resulting velocity
+    pub fn step(&mut self, map: &rc::Map, pos: &mut Vec2, wish: Vec2, item: usize) {
+        let speed: f32 = self.speed(item);
+        if self.velocity.length() > speed * 1.1 {
+            self.velocity = self.velocity * SLIDE_FRICTION + wish * speed * SLIDE_STEER;
+        } else if wish != Vec2::ZERO {
+            self.velocity += (wish * speed - self.velocity) * ACCELERATION;
+        } else {
+            self.velocity *= FRICTION;
+        }
+
+        if self.velocity.length() < 0.01 {
+            self.velocity = Vec2::ZERO;
+            return;
+        }
+
+        // Whatever the walls stop is lost
+        let next: Vec2 = rc::util::move_towards_collidable(map, *pos, *pos + self.velocity, self.velocity.length());
+        self.velocity = next - *pos;
+        *pos = next;
+    }
+
+    pub fn knockback(&mut self, impulse: Vec2) {
+        self.velocity += impulse;
+    }
+
+    /// Pushes the player at `pos` away from a blast at `origin`, weaker further out
+    pub fn blast(&mut self, pos: Vec2, origin: Vec2) {
+        let dist: f32 = pos.distance(origin);
+        if dist < BLAST_RADIUS {
+            self.knockback((pos - origin).normalize_or_zero() * BLAST_FORCE * (1. - dist / BLAST_RADIUS));
+        }
+    }
+
+    /// Distance moved per frame while carrying `item`
+    pub fn speed(&self, item: usize) -> f32 {
+        let mut speed: f32 = BASE_SPEED * ITEM_WEIGHTS[item];
+        if self.sprinting {
+            speed *= SPRINT_MULTIPLIER;
+        } else if self.crouching {
+            speed *= CROUCH_MULTIPLIER;
+        }
+
+        speed
+    }
+
+    /// Vertical view offset from crouching and head bob, negative is lower
+    pub fn eye_offset(&self) -> f32 {
+        self.bob_phase.sin() * self.bob_amp - self.crouch * CROUCH_DEPTH
+    }
+
+    pub fn stamina(&self) -> f32 {
+        self.stamina / MAX_STAMINA
+    }
+
+    pub fn exhausted(&self) -> bool {
+        self.exhausted
+    }
+}
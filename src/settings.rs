@@ -0,0 +1,223 @@
+use crate::input::Bindings;
+use crate::level::Difficulty;
+
+/// Choices the settings screen cycles through
+pub const SENSITIVITIES: [f32; 6] = [0.25, 0.5, 0.75, 1., 1.5, 2.];
+pub const VOLUMES: [f32; 5] = [0., 0.25, 0.5, 0.75, 1.];
+pub const RESOLUTIONS: [(i32, i32); 3] = [(600, 600), (800, 800), (1000, 1000)];
+pub const SHAKES: [f32; 3] = [0., 0.5, 1.];
+pub const CORPSE_LIMITS: [usize; 5] = [0, 10, 20, 50, 100];
+pub const DEADZONES: [f32; 5] = [0.05, 0.1, 0.15, 0.2, 0.3];
+pub const LOOK_ACCELS: [f32; 4] = [0., 0.5, 1., 2.];
+
+/// Options changed on the settings screen, kept in a config file between runs
+pub struct Settings {
+    pub difficulty: Difficulty,
+    pub sensitivity: f32,
+    pub volume: f32,
+    pub resolution: (i32, i32),
+    /// Multiplier on screen shake, 0 turns it off
+    pub shake: f32,
+    /// Corpses left on the floor before the oldest are cleared away
+    pub corpses: usize,
+    /// How far the gamepad sticks can move before they count, 0 to 1
+    pub deadzone: f32,
+    /// Extra right stick turn speed reached by holding it over, 0 turns it off
+    pub look_accel: f32,
+    pub bindings: Bindings,
+}
+
+impl Settings {
+    pub fn new() -> Self {
+        Self {
+            difficulty: Difficulty::Normal,
+            sensitivity: 0.5,
+            volume: 1.,
+            resolution: (800, 800),
+            shake: 1.,
+            corpses: 20,
+            deadzone: 0.15,
+            look_accel: 1.,
+            bindings: Bindings::new(),
+        }
+    }
+
+    /// Saved settings, anything missing or unreadable keeps its default
+    pub fn load() -> Self {
+        match storage::read() {
+            Some(text) => Self::parse(&text),
+            None => Settings::new(),
+        }
+    }
+
+    /// Settings from config file text, values the settings screen doesn't offer snap to the closest one it does
+    fn parse(text: &str) -> Self {
+        let mut settings: Settings = Settings::new();
+        for line in text.lines() {
+            let (key, value): (&str, &str) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => continue,
+            };
+
+            match key {
+                "difficulty" => settings.difficulty = Difficulty::from_name(value).unwrap_or(settings.difficulty),
+                "sensitivity" => settings.sensitivity = value.parse().unwrap_or(settings.sensitivity),
+                "volume" => settings.volume = value.parse().unwrap_or(settings.volume),
+                "resolution" => {
+                    if let Some((w, h)) = value.split_once('x').and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?))) {
+                        settings.resolution = (w, h);
+                    }
+                },
+                "shake" => settings.shake = value.parse().unwrap_or(settings.shake),
+                "corpses" => settings.corpses = value.parse().unwrap_or(settings.corpses),
+                "deadzone" => settings.deadzone = value.parse().unwrap_or(settings.deadzone),
+                "look-accel" => settings.look_accel = value.parse().unwrap_or(settings.look_accel),
+                _ => {
+                    if let Some(action) = key.strip_prefix("bind.") {
+                        settings.bindings.load(action, value);
+                    }
+                },
+            }
+        }
+
+        let gap = |a: f32, b: f32| -> f32 { (a - b).abs() };
+        settings.sensitivity = nearest(&SENSITIVITIES, settings.sensitivity, gap);
+        settings.volume = nearest(&VOLUMES, settings.volume, gap);
+        settings.resolution = nearest(&RESOLUTIONS, settings.resolution, |a, b| ((a.0 - b.0).abs() + (a.1 - b.1).abs()) as f32);
+        settings.shake = nearest(&SHAKES, settings.shake, gap);
+        settings.corpses = nearest(&CORPSE_LIMITS, settings.corpses, |a, b| a.abs_diff(b) as f32);
+        settings.deadzone = nearest(&DEADZONES, settings.deadzone, gap);
+        settings.look_accel = nearest(&LOOK_ACCELS, settings.look_accel, gap);
+        settings
+    }
+
+    pub fn save(&self) {
+        let mut text: String = format!(
+            "difficulty = {}\nsensitivity = {}\nvolume = {}\nresolution = {}x{}\nshake = {}\ncorpses = {}\ndeadzone = {}\nlook-accel = {}\n",
+            self.difficulty.name(), self.sensitivity, self.volume, self.resolution.0, self.resolution.1, self.shake,
+            self.corpses, self.deadzone, self.look_accel
+        );
+        text.push_str(&self.bindings.save());
+        storage::write(&text);
+    }
+}
+
+/// The option after `current` in `options`, wrapping around
+pub fn cycle<T: PartialEq + Copy>(options: &[T], current: T) -> T {
+    let next: usize = options.iter().position(|option| *option == current).map_or(0, |i| (i + 1) % options.len());
+    options[next]
+}
+
+/// The option closest to `value` by `distance`, the first one for values that aren't numbers
+fn nearest<T: Copy>(options: &[T], value: T, distance: impl Fn(T, T) -> f32) -> T {
+    options.iter().copied().min_by(|a, b| {
+        let (a, b) = (distance(*a, value), distance(*b, value));
+        // NaN sorts last so a NaN value falls back to the first option
+        a.is_nan().cmp(&b.is_nan()).then(a.total_cmp(&b))
+    }).unwrap_or(value)
+}
+
+/// Config file in the platform's config directory
+#[cfg(not(target_arch = "wasm32"))]
+mod storage {
+    use std::path::PathBuf;
+
+    fn path() -> Option<PathBuf> {
+        let dir: PathBuf = if cfg!(windows) {
+            PathBuf::from(std::env::var_os("APPDATA")?)
+        } else if cfg!(target_os = "macos") {
+            PathBuf::from(std::env::var_os("HOME")?).join("Library").join("Application Support")
+        } else {
+            match std::env::var_os("XDG_CONFIG_HOME") {
+                Some(dir) => PathBuf::from(dir),
+                None => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+            }
+        };
+
+        Some(dir.join("raycast-rs").join("settings.cfg"))
+    }
+
+    pub fn read() -> Option<String> {
+        std::fs::read_to_string(path()?).ok()
+    }
+
+    pub fn write(text: &str) {
+        let path: PathBuf = match path() {
+            Some(path) => path,
+            None => return,
+        };
+
+        let res: std::io::Result<()> = path.parent().map_or(Ok(()), std::fs::create_dir_all).and_then(|_| std::fs::write(&path, text));
+        if let Err(err) = res {
+            eprintln!("couldn't save settings to {}: {}", path.display(), err);
+        }
+    }
+}
+
+/// localStorage, through the plugin registered in docs/index.html
+#[cfg(target_arch = "wasm32")]
+mod storage {
+    extern "C" {
+        fn config_len() -> usize;
+        fn config_read(ptr: *mut u8);
+        fn config_write(ptr: *const u8, len: usize);
+    }
+
+    pub fn read() -> Option<String> {
+        let len: usize = unsafe { config_len() };
+        if len == 0 {
+            return None;
+        }
+
+        let mut bytes: Vec<u8> = vec![0; len];
+        unsafe { config_read(bytes.as_mut_ptr()) };
+        String::from_utf8(bytes).ok()
+    }
+
+    pub fn write(text: &str) {
+        unsafe { config_write(text.as_ptr(), text.len()) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cycle_wraps_around() {
+        assert_eq!(cycle(&VOLUMES, 0.25), 0.5);
+        assert_eq!(cycle(&VOLUMES, 1.), 0.);
+        assert_eq!(cycle(&CORPSE_LIMITS, 100), 0);
+        // Values that aren't options start over from the first
+        assert_eq!(cycle(&VOLUMES, 0.3), 0.);
+    }
+
+    #[test]
+    fn parse_keeps_offered_values() {
+        let settings: Settings = Settings::parse("sensitivity = 1.5\nvolume = 0.25\nresolution = 1000x1000\ncorpses = 50\ndeadzone = 0.3\nlook-accel = 2\n");
+        assert_eq!(settings.sensitivity, 1.5);
+        assert_eq!(settings.volume, 0.25);
+        assert_eq!(settings.resolution, (1000, 1000));
+        assert_eq!(settings.corpses, 50);
+        assert_eq!(settings.deadzone, 0.3);
+        assert_eq!(settings.look_accel, 2.);
+    }
+
+    #[test]
+    fn parse_snaps_bad_values() {
+        let settings: Settings = Settings::parse("sensitivity = -3\nvolume = 5\nresolution = 0x0\nshake = nan\ncorpses = 1000\ndeadzone = 1\nlook-accel = -1\n");
+        assert_eq!(settings.sensitivity, SENSITIVITIES[0]);
+        assert_eq!(settings.volume, 1.);
+        assert_eq!(settings.resolution, RESOLUTIONS[0]);
+        assert_eq!(settings.shake, SHAKES[0]);
+        assert_eq!(settings.corpses, 100);
+        assert_eq!(settings.deadzone, 0.3);
+        assert_eq!(settings.look_accel, 0.);
+    }
+
+    #[test]
+    fn parse_ignores_junk() {
+        let settings: Settings = Settings::parse("volume\nvolume = loud\nnonsense = 1\n");
+        assert_eq!(settings.volume, Settings::new().volume);
+    }
+}
@@ -0,0 +1,85 @@
+use crate::layout::{self, Layout};
+use raycast::prelude as rc;
+use rc::prelude::{macroquad, glam};
+use macroquad::prelude as mq;
+use glam::IVec2;
+use std::collections::HashMap;
+
+/// Field of view of the engine's renderer
+pub const FOV: f32 = std::f32::consts::FRAC_PI_3;
+
+const SKY: [u8; 4] = [150, 190, 235, 255];
+
+/// Floor and ceiling of every tile as an `rc::Surface`
+///
+/// The engine draws one floor and one ceiling for the whole map, so the tile the camera
+/// stands on picks them
+pub struct Surfaces {
+    w: i32,
+    h: i32,
+    /// Color of every tile, `None` keeps the map's default
+    floors: Vec<Option<[u8; 4]>>,
+    ceilings: Vec<Option<[u8; 4]>>,
+}
+
+impl Surfaces {
+    pub fn new(layout: &Layout) -> Self {
+        let h: i32 = layout.grid.len() as i32;
+        let w: i32 = layout.grid.first().map_or(0, |row| row.len()) as i32;
+
+        let mut colors: HashMap<String, [u8; 4]> = HashMap::new();
+        let mut color = |name: &str| -> [u8; 4] {
+            *colors.entry(name.to_string()).or_insert_with(|| layout::surface_image(name).map_or(SKY, |image| average(&image)))
+        };
+
+        let mut floors: Vec<Option<[u8; 4]>> = vec![None; (w * h) as usize];
+        let mut ceilings: Vec<Option<[u8; 4]>> = vec![None; (w * h) as usize];
+        for y in 0..h {
+            for x in 0..w {
+                let i: usize = (y * w + x) as usize;
+                if let Some(texture) = layout::hazard_texture(layout.at(x, y)) {
+                    floors[i] = Some(color(texture));
+                } else if let Some(region) = layout.floor(IVec2::new(x, y)) {
+                    floors[i] = Some(color(&region.texture));
+                }
+
+                if let Some(region) = layout.ceiling(IVec2::new(x, y)) {
+                    ceilings[i] = Some(color(&region.texture));
+                }
+            }
+        }
+
+        Self {
+            w,
+            h,
+            floors,
+            ceilings,
+        }
+    }
+
+    /// Gives the map the floor and ceiling of the tile at `pos`
+    pub fn apply(&self, map: &mut rc::Map, pos: IVec2) {
+        let (floor, ceiling) = if pos.x >= 0 && pos.y >= 0 && pos.x < self.w && pos.y < self.h {
+            let i: usize = (pos.y * self.w + pos.x) as usize;
+            (self.floors[i], self.ceilings[i])
+        } else {
+            (None, None)
+        };
+
+        map.floor_tex(rc::Surface::Color(floor.unwrap_or(mq::DARKGRAY.into())));
+        map.ceil_tex(rc::Surface::Color(ceiling.unwrap_or(mq::GRAY.into())));
+    }
+}
+
+/// Average color of a texture, what it looks like from a distance
+fn average(image: &mq::Image) -> [u8; 4] {
+    let mut sum: [u64; 3] = [0; 3];
+    for px in image.bytes.chunks_exact(4) {
+        for (total, c) in sum.iter_mut().zip(px) {
+            *total += *c as u64;
+        }
+    }
+
+    let count: u64 = (image.bytes.len() / 4).max(1) as u64;
+    [(sum[0] / count) as u8, (sum[1] / count) as u8, (sum[2] / count) as u8, 255]
+}
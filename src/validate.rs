@@ -0,0 +1,242 @@
+use crate::layout::{self, Action, Directive, Layout, Reach};
+use raycast::prelude as rc;
+use rc::prelude::glam;
+use glam::IVec2;
+use std::collections::HashSet;
+
+/// The player is assumed to find every key and shoot through every breakable wall
+const REACH: Reach = Reach {
+    doors: true,
+    breakable: true,
+};
+
+/// Problem found in a map file, `line` and `column` count from 1 and are 0 when it has no place
+struct Diagnostic {
+    line: usize,
+    column: usize,
+    message: String,
+}
+
+impl Diagnostic {
+    fn at(pos: IVec2, message: String) -> Self {
+        Self {
+            line: pos.y as usize + 1,
+            column: pos.x as usize + 1,
+            message,
+        }
+    }
+}
+
+/// `validate-map <file>...`, prints diagnostics and returns the process exit code
+pub fn run(paths: &[String]) -> i32 {
+    if paths.is_empty() {
+        eprintln!("usage: validate-map <file>...");
+        return 2;
+    }
+
+    let mut failed: bool = false;
+    for path in paths {
+        let bytes: Vec<u8> = match std::fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                eprintln!("{}: {}", path, err);
+                failed = true;
+                continue;
+            },
+        };
+
+        let diagnostics: Vec<Diagnostic> = validate(&bytes);
+        for diagnostic in &diagnostics {
+            if diagnostic.line == 0 {
+                eprintln!("{}: {}", path, diagnostic.message);
+            } else {
+                eprintln!("{}:{}:{}: {}", path, diagnostic.line, diagnostic.column, diagnostic.message);
+            }
+        }
+
+        if diagnostics.is_empty() {
+            println!("{}: ok", path);
+        } else {
+            failed = true;
+        }
+    }
+
+    if failed { 1 } else { 0 }
+}
+
+fn validate(bytes: &[u8]) -> Vec<Diagnostic> {
+    let mut res: Vec<Diagnostic> = Vec::new();
+    let layout: Layout = Layout::from_bytes(bytes);
+    let grid: &Vec<Vec<char>> = &layout.grid;
+    if grid.is_empty() {
+        res.push(Diagnostic { line: 0, column: 0, message: String::from("map has no grid") });
+        return res;
+    }
+
+    let walls: HashSet<char> = layout.walls.iter().map(|wall| wall.tile).collect();
+
+    // Directives start after the grid and the blank line ending it
+    let text: String = String::from_utf8_lossy(bytes).into_owned();
+    for (i, line) in text.lines().enumerate().skip(grid.len() + 1) {
+        let problems: Vec<String> = match layout::parse_directive(line.trim_end_matches('\r')) {
+            Ok(Some(directive)) => check_directive(&layout, &walls, &directive),
+            Ok(None) => Vec::new(),
+            Err(err) => vec![err],
+        };
+        res.extend(problems.into_iter().map(|message| Diagnostic { line: i + 1, column: 1, message }));
+    }
+
+    let width: usize = grid[0].len();
+    for (y, row) in grid.iter().enumerate() {
+        if row.len() != width {
+            res.push(Diagnostic::at(IVec2::new(row.len().min(width) as i32, y as i32), format!("row is {} wide, the first row is {}", row.len(), width)));
+        }
+
+        for (x, tile) in row.iter().enumerate() {
+            let pos: IVec2 = IVec2::new(x as i32, y as i32);
+            let border: bool = x == 0 || y == 0 || x + 1 == row.len() || y + 1 == grid.len();
+            if !walls.contains(tile) && !layout::GRID_TILES.contains(*tile) {
+                res.push(Diagnostic::at(pos, format!("unknown tile '{}', declare it with a wall directive", tile)));
+            } else if border && !walls.contains(tile) {
+                res.push(Diagnostic::at(pos, format!("outer wall is open, found '{}'", tile)));
+            }
+        }
+    }
+
+    let starts: Vec<IVec2> = layout.find('P');
+    let start: IVec2 = match starts.as_slice() {
+        [] => {
+            res.push(Diagnostic { line: 0, column: 0, message: String::from("no player start 'P'") });
+            return res;
+        },
+        [start] => *start,
+        [start, others @ ..] => {
+            for pos in others {
+                res.push(Diagnostic::at(*pos, String::from("extra player start, only the first 'P' is used")));
+            }
+            *start
+        },
+    };
+
+    let reached: HashSet<IVec2> = layout.flood(start, REACH);
+    let mut reported: HashSet<IVec2> = HashSet::new();
+    for (y, row) in grid.iter().enumerate() {
+        for x in 0..row.len() {
+            let pos: IVec2 = IVec2::new(x as i32, y as i32);
+            if !layout::is_floor(layout.at(pos.x, pos.y)) || reached.contains(&pos) || reported.contains(&pos) {
+                continue;
+            }
+
+            // One diagnostic per sealed-off pocket, at its first cell
+            let pocket: HashSet<IVec2> = layout.flood(pos, REACH);
+            let cells: usize = pocket.iter().filter(|cell| layout::is_floor(layout.at(cell.x, cell.y)) && !reached.contains(cell)).count();
+            res.push(Diagnostic::at(pos, format!("{} floor cells can't be reached from the player start", cells)));
+            reported.extend(pocket);
+        }
+    }
+
+    res
+}
+
+/// Problems with a directive that parsed but doesn't fit the grid it's for
+fn check_directive(layout: &Layout, walls: &HashSet<char>, directive: &Directive) -> Vec<String> {
+    let mut res: Vec<String> = Vec::new();
+    let mut actions: &[Action] = &[];
+    match directive {
+        Directive::Wall(wall) => {
+            if let Some((into, _)) = wall.breaks {
+                if into != '.' && !walls.contains(&into) {
+                    res.push(format!("wall '{}' breaks into '{}', which is neither '.' nor a declared wall", wall.tile, into));
+                }
+            }
+        }
+        Directive::Trigger(trigger) => {
+            if !inside(layout, trigger.min) || !inside(layout, trigger.max) {
+                res.push(String::from("trigger area goes outside the grid"));
+            } else if !(trigger.min.y..=trigger.max.y).any(|y| (trigger.min.x..=trigger.max.x).any(|x| layout.passable(IVec2::new(x, y), REACH))) {
+                res.push(String::from("trigger area has no cell the player can walk into"));
+            }
+            actions = &trigger.actions;
+        }
+        Directive::Switch(switch) => {
+            if layout.at(switch.pos.x, switch.pos.y) != 'S' {
+                res.push(format!("switch at {} {} isn't on an 'S' tile", switch.pos.x, switch.pos.y));
+            }
+            actions = &switch.actions;
+        }
+        Directive::Teleporter(teleporter) => {
+            if !layout.passable(teleporter.pos, REACH) {
+                res.push(format!("teleporter pad at {} {} is inside a wall", teleporter.pos.x, teleporter.pos.y));
+            }
+            if !layout.passable(teleporter.dest, REACH) {
+                res.push(format!("teleporter lands inside a wall at {} {}", teleporter.dest.x, teleporter.dest.y));
+            }
+        }
+        Directive::Floor(_) | Directive::Ceiling(_) => (),
+    }
+
+    for action in actions {
+        if let Action::Open(pos) = action {
+            if !layout::is_door(layout.at(pos.x, pos.y)) {
+                res.push(format!("'open {} {}' doesn't point at a door", pos.x, pos.y));
+            }
+        }
+    }
+
+    res
+}
+
+fn inside(layout: &Layout, pos: IVec2) -> bool {
+    pos.y >= 0 && pos.x >= 0 && layout.grid.get(pos.y as usize).is_some_and(|row| (pos.x as usize) < row.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(map: &str) -> Vec<String> {
+        validate(map.as_bytes()).into_iter().map(|diagnostic| diagnostic.message).collect()
+    }
+
+    fn fails(map: &str, expected: &str) {
+        let messages: Vec<String> = messages(map);
+        assert!(messages.iter().any(|message| message.contains(expected)), "expected '{}', got {:?}", expected, messages);
+    }
+
+    #[test]
+    fn good_map() {
+        let map: &str = "0000000\n0P.|.S0\n0.T.1.0\n0000000\n\nwall 1 brick 1 breaks .\nswitch 5 1 open 3 1\ntrigger 1 2 1 1 message hi\nteleport 2 2 4 1 0\n";
+        assert_eq!(messages(map), Vec::<String>::new());
+    }
+
+    #[test]
+    fn grid_problems() {
+        fails("", "map has no grid");
+        fails("000\n0.0\n000\n", "no player start");
+        fails("000\n0P.\n000\n", "outer wall is open");
+        fails("0000\n0Pz0\n0000\n", "unknown tile 'z'");
+        fails("0000\n0P0\n0000\n", "row is 3 wide");
+        fails("00000\n0P0.0\n00000\n", "1 floor cells can't be reached");
+        fails("000000\n0P0R.0\n000000\n", "2 floor cells can't be reached");
+        fails("00000\n0P0R0\n00000\n", "1 floor cells can't be reached");
+    }
+
+    #[test]
+    fn breakable_walls_count_as_passable() {
+        let map: &str = "00000\n0P1.0\n00000\n\nwall 1 brick 1 breaks .\n";
+        assert_eq!(messages(map), Vec::<String>::new());
+    }
+
+    #[test]
+    fn directive_problems() {
+        let grid: &str = "000000\n0P.S|0\n000000\n\n";
+        fails(&format!("{}wall 1 brick 1 breaks 9\n", grid), "breaks into '9'");
+        fails(&format!("{}switch 2 1 end\n", grid), "isn't on an 'S' tile");
+        fails(&format!("{}switch 3 1 open 2 1\n", grid), "doesn't point at a door");
+        fails(&format!("{}trigger 0 0 1 1 end\n", grid), "no cell the player can walk into");
+        fails(&format!("{}trigger 4 1 3 1 end\n", grid), "outside the grid");
+        fails(&format!("{}teleport 0 0 1 1 0\n", grid), "pad at 0 0 is inside a wall");
+        fails(&format!("{}teleport 1 1 0 1 0\n", grid), "lands inside a wall");
+        fails(&format!("{}floor 1 1 1 1 marble\n", grid), "unknown floor texture");
+    }
+}